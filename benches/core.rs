@@ -0,0 +1,97 @@
+//! Benchmarks of the pure parsing and ranking logic on synthetic data sized
+//! like large remote provider lists.
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::{fmt::Write as _, hint::black_box, time::Duration};
+use wisp::{
+    cli::Cli, measurement::Measurement, providers::Provider, scutil::parse_dns_output, selection,
+};
+
+/// Numbers of resolvers or providers each benchmark is run with.
+const SIZES: [usize; 3] = [10, 100, 1000];
+
+/// `scutil --dns` output with `count` resolvers, half of them scoped.
+fn scutil_output(count: usize) -> String {
+    let mut output = String::from("\nDNS configuration\n\n");
+    for index in 0..count {
+        if index == count / 2 {
+            output.push_str("DNS configuration (for scoped queries)\n\n");
+        }
+        let _ = write!(
+            output,
+            "resolver #{}\n  search domain[0] : corp{}.example\n  nameserver[0] : 10.0.{}.{}\n  \
+             nameserver[1] : fd00::{:x}\n  if_index : 6 (en0)\n  flags    : Request A records\n  \
+             reach    : 0x00000002 (Reachable)\n\n",
+            index + 1,
+            index,
+            index / 256,
+            index % 256,
+            index
+        );
+    }
+    output
+}
+
+/// `count` providers with deterministic, varied measurements.
+fn providers(count: usize) -> Vec<(Provider, Measurement)> {
+    let mut rng = fastrand::Rng::with_seed(7);
+    (0..count)
+        .map(|index| {
+            let provider = Provider::new(
+                &format!("Provider {}", index),
+                &format!("10.{}.{}.1", index / 256, index % 256),
+                "Synthetic provider",
+            );
+            let samples = (0..rng.usize(0..=3))
+                .map(|_| Duration::from_micros(rng.u64(1_000..200_000)))
+                .collect();
+            let measurement = Measurement {
+                samples,
+                sent: 3,
+                cold: None,
+                setup: None,
+                ttl: None,
+                minimizes: None,
+            };
+            (provider, measurement)
+        })
+        .collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_dns_output");
+    for size in SIZES {
+        let output = scutil_output(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &output, |b, output| {
+            b.iter(|| parse_dns_output(black_box(output)))
+        });
+    }
+    group.finish();
+}
+
+fn rank(c: &mut Criterion) {
+    for name in ["fastest", "lowest-loss", "composite", "sticky"] {
+        let cli = Cli::parse_from(["wisp", "--strategy", name]);
+        let strategy = selection::from_cli(&cli, vec!["10.0.1.1".to_string()]);
+        let mut group = c.benchmark_group(format!("select/{}", name));
+        for size in SIZES {
+            let data = providers(size);
+            group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+                b.iter_batched(
+                    || {
+                        data.iter()
+                            .map(|(provider, measurement)| (provider, measurement.clone()))
+                            .collect::<Vec<_>>()
+                    },
+                    |mut results| strategy.select(&mut results).len(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse, rank);
+criterion_main!(benches);
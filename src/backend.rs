@@ -0,0 +1,745 @@
+//! Ways of measuring a DNS server's latency, selected with `--method`.
+
+use crate::{
+    cli::{Cli, Method, RecordType},
+    dns,
+    error::Error,
+    logging::{log, warn},
+    measurement::Measurement,
+    mock::MockLatencies,
+    ping::parse_ping_output,
+    pop,
+    providers::Provider,
+    proxy::Proxy,
+    source, system,
+    tls::{self, TlsStream},
+};
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    net::{SocketAddr, ToSocketAddrs},
+    process::Command,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Number of probes whose round-trip times make up one measurement.
+pub const PING_COUNT: usize = 3;
+
+/// Default number of probes per server when ranking by a percentile.
+pub const PERCENTILE_PING_COUNT: usize = 20;
+
+/// How long to wait for the answer to a single query.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Query timeout for scripted runs, where nobody waits on slow servers.
+const QUERY_TIMEOUT_SCRIPTED: Duration = Duration::from_secs(1);
+
+/// Port DNS-over-TLS servers listen on.
+const DOT_PORT: u16 = 853;
+
+/// Port DNS-over-HTTPS servers listen on.
+const DOH_PORT: u16 = 443;
+
+/// Settings shared by every probe of a run.
+#[derive(Clone)]
+pub struct ProbeSettings {
+    /// Number of measured probes per server
+    pub count: usize,
+    /// Number of discarded probes sent first
+    pub warmup: u32,
+    /// How long to wait for each probe
+    pub timeout: Duration,
+    /// Methods to measure with; the first one drives the ranking
+    pub methods: Vec<Method>,
+    /// Try the methods in order and keep the first that gets answers
+    /// instead of measuring with all of them (`--transport`)
+    pub fallback: bool,
+    /// Number of servers measured at the same time
+    pub max_concurrent: usize,
+    /// Log what each probe command printed next to the parsed result
+    pub raw_ping_output: bool,
+    /// Domain looked up by query-based methods
+    pub probe_name: String,
+    /// Record type code those lookups ask for, e.g. 28 for `AAAA`
+    pub record_type: u16,
+    /// Record types measured one after another and combined instead
+    /// (`--record-types`), empty to measure `record_type` only
+    pub record_types: Vec<RecordType>,
+    /// How often a timed-out probe is retried before it counts as lost
+    pub timeout_retries: u32,
+    /// Least time between starting two probes to the same server
+    pub probe_interval: Duration,
+    /// Port probed instead of the method's standard one
+    pub port: Option<u16>,
+    /// Proxy the TLS-based methods connect through
+    pub proxy: Option<Proxy>,
+    /// Send one discarded probe before the first server is measured
+    pub global_warmup: bool,
+    /// Log which instance (`id.server`) answered next to every probe
+    pub log_pop: bool,
+    /// Predetermined measurements used instead of probing (`--mock-latencies`)
+    pub mock: Option<MockLatencies>,
+    /// Round-robin passes of one probe per server instead of `count` probes
+    /// in a row (`--interleave`)
+    pub interleave: Option<usize>,
+}
+
+impl ProbeSettings {
+    /// Settings for this run; without `--timeout`, scripted runs get a
+    /// shorter timeout than interactive ones.
+    pub fn from_cli(cli: &Cli) -> Self {
+        let timeout = match cli.timeout {
+            Some(ms) => Duration::from_millis(ms),
+            None if system::is_interactive() => QUERY_TIMEOUT,
+            None => QUERY_TIMEOUT_SCRIPTED,
+        };
+        ProbeSettings {
+            count: cli.samples.unwrap_or(match cli.rank_percentile {
+                Some(_) => PERCENTILE_PING_COUNT,
+                None => PING_COUNT,
+            }),
+            warmup: cli.warmup,
+            timeout,
+            methods: if cli.transport.is_empty() {
+                cli.method.clone()
+            } else {
+                cli.transport.clone()
+            },
+            fallback: !cli.transport.is_empty(),
+            max_concurrent: cli.max_concurrent,
+            raw_ping_output: cli.raw_ping_output,
+            probe_name: cli.probe_domain.clone(),
+            record_type: cli.record_type.code(),
+            record_types: cli.record_types.clone(),
+            timeout_retries: cli.timeout_retries,
+            probe_interval: Duration::from_millis(cli.probe_interval),
+            proxy: cli.proxy.clone(),
+            global_warmup: !cli.no_global_warmup && cli.mock_latencies.is_none(),
+            log_pop: cli.log_pop,
+            mock: cli.mock_latencies.clone(),
+            interleave: cli.interleave,
+            port: None,
+        }
+    }
+
+    /// The settings `provider` is probed with: its own port, and only its
+    /// own protocol when the provider list gave one.
+    pub fn for_provider(&self, provider: &Provider) -> Cow<'_, ProbeSettings> {
+        if provider.port.is_none() && provider.protocol.is_none() {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(ProbeSettings {
+            port: provider.port,
+            methods: provider
+                .protocol
+                .map_or_else(|| self.methods.clone(), |protocol| vec![protocol]),
+            ..self.clone()
+        })
+    }
+}
+
+/// A way of measuring how quickly a DNS server responds.
+pub trait MeasurementBackend {
+    /// Short protocol label used in logs, e.g. "udp".
+    fn name(&self) -> &'static str;
+
+    /// Sends `settings.warmup` discarded probes to `server`, then
+    /// `settings.count` measured ones.
+    ///
+    /// Unanswered probes count as lost; only a failure that says nothing
+    /// about the server (e.g. `ping` missing) is an error.
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error>;
+}
+
+/// Returns the backend implementing `method`.
+pub fn backend(method: Method) -> &'static dyn MeasurementBackend {
+    match method {
+        Method::Icmp => &Icmp,
+        Method::Udp => &Udp,
+        Method::Tcp => &Tcp,
+        Method::Dot => &Dot,
+        Method::Doh => &Doh,
+    }
+}
+
+/// ICMP echo through the system `ping`.
+struct Icmp;
+
+impl MeasurementBackend for Icmp {
+    fn name(&self) -> &'static str {
+        "icmp"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        if settings.warmup > 0 {
+            ping(server, settings.warmup as usize, settings)?;
+        }
+        let (output, errors) = ping(server, settings.count, settings)?;
+        let mut measurement = parse_ping_output(&output, settings.count);
+        // Lost pings are resent, up to `timeout_retries` rounds, so a
+        // transient drop does not count as loss
+        for _ in 0..settings.timeout_retries {
+            let missing = measurement.sent.saturating_sub(measurement.samples.len());
+            if missing == 0 {
+                break;
+            }
+            let (retry, _) = ping(server, missing, settings)?;
+            measurement
+                .samples
+                .extend(parse_ping_output(&retry, missing).samples);
+        }
+        if settings.log_pop {
+            for (index, sample) in measurement.samples.iter().enumerate() {
+                log_pop(self, server, index + 1, *sample, settings);
+            }
+        }
+        if settings.raw_ping_output {
+            log(&format!(
+                "Raw ping output for {}:\n--- stdout ---\n{}--- stderr ---\n{}--- parsed: {} of {} replies, {:?} ---",
+                server,
+                output,
+                errors,
+                measurement.samples.len(),
+                measurement.sent,
+                measurement.samples
+            ));
+        }
+        Ok(measurement)
+    }
+}
+
+/// Flag of the system `ping` that sets the source address.
+const PING_SOURCE_FLAG: &str = if cfg!(target_os = "macos") {
+    "-S"
+} else {
+    "-I"
+};
+
+/// Flag of the system `ping` that caps its total run time in seconds.
+const PING_DEADLINE_FLAG: &str = if cfg!(target_os = "macos") {
+    "-t"
+} else {
+    "-w"
+};
+
+/// The value of the system `ping`'s `-W` flag for waiting `timeout` on each
+/// reply: milliseconds on macOS, whole seconds (rounded up) on Linux.
+fn ping_wait(timeout: Duration) -> String {
+    if cfg!(target_os = "macos") {
+        timeout.as_millis().to_string()
+    } else {
+        (timeout.as_millis().div_ceil(1000) as u64)
+            .max(1)
+            .to_string()
+    }
+}
+
+/// Runs `ping -c <count> -W <timeout> -t <deadline>`, from the `--source`
+/// address or through the `--vrf` device if one was given, and returns its
+/// stdout and stderr. ping waits a second between pings, or the
+/// `--probe-interval` rounded up to whole seconds when that is longer.
+///
+/// The deadline leaves room for the time between pings plus one timeout,
+/// so a server that drops everything costs about as long as one that
+/// answers rather than a full timeout per packet.
+///
+/// A non-zero exit status only means packets were lost, so just a failure
+/// to start ping is reported as an error.
+fn ping(dns: &str, count: usize, settings: &ProbeSettings) -> Result<(String, String), Error> {
+    let timeout = settings.timeout;
+    let interval_secs = (settings.probe_interval.as_millis().div_ceil(1000) as u64).max(1);
+    let deadline = (count.saturating_sub(1) as u64 * interval_secs
+        + timeout.as_millis().div_ceil(1000) as u64)
+        .max(1)
+        .to_string();
+    let count = count.to_string();
+    let wait = ping_wait(timeout);
+    let interval = interval_secs.to_string();
+    let mut args = vec!["-c", &count, "-W", &wait, PING_DEADLINE_FLAG, &deadline];
+    if interval_secs > 1 {
+        args.extend(["-i", &interval]);
+    }
+    let source = source::address().map(|ip| ip.to_string());
+    if let Some(source) = &source {
+        args.extend([PING_SOURCE_FLAG, source]);
+    }
+    if let Some(vrf) = source::vrf() {
+        args.extend(["-I", vrf]);
+    }
+    args.push(dns);
+    let output = Command::new("ping")
+        .args(&args)
+        .output()
+        .map_err(|err| Error::Command {
+            command: format!("ping {}", args.join(" ")),
+            message: err.to_string(),
+        })?;
+    Ok((
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    ))
+}
+
+/// Lookups through the operating system's resolver with `getaddrinfo`, its
+/// cache, search domains and every configured server included, timed end
+/// to end for the `--compare-to-system` baseline. The server is ignored.
+pub struct SystemResolver;
+
+impl MeasurementBackend for SystemResolver {
+    fn name(&self) -> &'static str {
+        "getaddrinfo"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        // There is no server to ask for its instance
+        let settings = &ProbeSettings {
+            log_pop: false,
+            ..settings.clone()
+        };
+        Ok(repeat(self, server, settings, || {
+            system_lookup(&settings.probe_name, settings.timeout)
+        }))
+    }
+}
+
+/// Resolves `name` with `getaddrinfo` on a helper thread, as the call has
+/// no timeout of its own, and gives up on it after `timeout`.
+fn system_lookup(name: &str, timeout: Duration) -> io::Result<Duration> {
+    let (sender, receiver) = mpsc::channel();
+    let name = name.to_string();
+    thread::spawn(move || {
+        let start = Instant::now();
+        let found = (name.as_str(), 0)
+            .to_socket_addrs()
+            .map(|mut addresses| addresses.next().is_some());
+        let _ = sender.send(found.map(|found| (found, start.elapsed())));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok((true, elapsed))) => Ok(elapsed),
+        Ok(Ok((false, _))) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no addresses returned",
+        )),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "getaddrinfo timed out",
+        )),
+    }
+}
+
+/// Plain DNS queries over UDP, one socket per query.
+struct Udp;
+
+impl MeasurementBackend for Udp {
+    fn name(&self) -> &'static str {
+        "udp"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        let address = match server_address(server, settings) {
+            Ok(address) => address,
+            Err(err) => return Ok(unreachable(self, server, settings, err)),
+        };
+        Ok(repeat(self, server, settings, || {
+            dns::query(
+                address,
+                &settings.probe_name,
+                settings.record_type,
+                settings.timeout,
+            )
+        }))
+    }
+}
+
+/// DNS queries over a TCP connection kept open across probes.
+struct Tcp;
+
+impl MeasurementBackend for Tcp {
+    fn name(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        let address = match server_address(server, settings) {
+            Ok(address) => address,
+            Err(err) => return Ok(unreachable(self, server, settings, err)),
+        };
+        Ok(repeat_on_connection(
+            self,
+            server,
+            settings,
+            None,
+            || {
+                let stream = source::tcp_connect(address, settings.timeout)?;
+                stream.set_read_timeout(Some(settings.timeout))?;
+                stream.set_write_timeout(Some(settings.timeout))?;
+                Ok(stream)
+            },
+            |stream| {
+                dns::exchange_stream(stream, &settings.probe_name, settings.record_type).map(drop)
+            },
+        ))
+    }
+}
+
+/// DNS-over-TLS (RFC 7858) queries on a session kept open across probes.
+struct Dot;
+
+impl MeasurementBackend for Dot {
+    fn name(&self) -> &'static str {
+        "dot"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        let (host, address) = match tls_endpoint(server, settings.port.unwrap_or(DOT_PORT)) {
+            Ok(endpoint) => endpoint,
+            Err(err) => return Ok(unreachable(self, server, settings, err)),
+        };
+        Ok(repeat_on_connection(
+            self,
+            server,
+            settings,
+            None,
+            || tls::connect(host, address, settings.proxy.as_ref(), settings.timeout),
+            |stream| {
+                dns::exchange_stream(stream, &settings.probe_name, settings.record_type).map(drop)
+            },
+        ))
+    }
+}
+
+/// DNS-over-HTTPS (RFC 8484) POST requests on a connection kept alive across
+/// probes.
+///
+/// The first request on a fresh connection pays for the TCP and TLS
+/// handshakes, so it is reported separately as the cold latency; the
+/// measured samples are the warm requests reusing that connection.
+struct Doh;
+
+impl MeasurementBackend for Doh {
+    fn name(&self) -> &'static str {
+        "doh"
+    }
+
+    fn measure(&self, server: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+        let (host, address) = match tls_endpoint(server, settings.port.unwrap_or(DOH_PORT)) {
+            Ok(endpoint) => endpoint,
+            Err(err) => return Ok(unreachable(self, server, settings, err)),
+        };
+        let start = Instant::now();
+        let (cold, connection) =
+            match tls::connect(host, address, settings.proxy.as_ref(), settings.timeout)
+                .map(|stream| (stream, start.elapsed()))
+                .and_then(|(mut stream, setup)| {
+                    doh_exchange(&mut stream, host, settings).map(|()| (stream, setup))
+                }) {
+                Ok(connection) => (Some(start.elapsed()), Some(connection)),
+                Err(_) => (None, None),
+            };
+        let measurement = repeat_on_connection(
+            self,
+            server,
+            settings,
+            connection,
+            || tls::connect(host, address, settings.proxy.as_ref(), settings.timeout),
+            |stream| doh_exchange(stream, host, settings),
+        );
+        Ok(Measurement {
+            cold,
+            ..measurement
+        })
+    }
+}
+
+/// Looks up `name` on `server` as `settings` probe it, for the checks that
+/// look at the answers: on its port, over the first method that sends
+/// queries (plain UDP when only pinged).
+pub fn lookup(
+    server: &str,
+    name: &str,
+    record_type: u16,
+    settings: &ProbeSettings,
+) -> io::Result<dns::Response> {
+    let method = settings
+        .methods
+        .iter()
+        .copied()
+        .find(|method| *method != Method::Icmp)
+        .unwrap_or(Method::Udp);
+    let timeout = settings.timeout;
+    let packet = match method {
+        Method::Icmp | Method::Udp => {
+            return dns::lookup_record(
+                server_address(server, settings)?,
+                name,
+                record_type,
+                timeout,
+            )
+        }
+        Method::Tcp => {
+            let mut stream = source::tcp_connect(server_address(server, settings)?, timeout)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            dns::exchange_stream(&mut stream, name, record_type)?
+        }
+        Method::Dot => {
+            let (host, address) = tls_endpoint(server, settings.port.unwrap_or(DOT_PORT))?;
+            let mut stream = tls::connect(host, address, settings.proxy.as_ref(), timeout)?;
+            dns::exchange_stream(&mut stream, name, record_type)?
+        }
+        Method::Doh => {
+            let (host, address) = tls_endpoint(server, settings.port.unwrap_or(DOH_PORT))?;
+            let mut stream = tls::connect(host, address, settings.proxy.as_ref(), timeout)?;
+            doh_query(&mut stream, host, name, record_type)?
+        }
+    };
+    dns::parse(&packet)
+}
+
+/// Resolves a server address to the socket address plain DNS is sent to:
+/// its DNS port, unless the provider list gave another.
+fn server_address(server: &str, settings: &ProbeSettings) -> io::Result<SocketAddr> {
+    let mut address = dns::resolve_server(server)?;
+    if let Some(port) = settings.port {
+        address.set_port(port);
+    }
+    Ok(address)
+}
+
+/// Splits a server address into the name its certificate is checked against
+/// (the address without an IPv6 zone) and the address to connect to on
+/// `port`, which keeps the zone.
+fn tls_endpoint(server: &str, port: u16) -> io::Result<(&str, SocketAddr)> {
+    let host = server.split('%').next().unwrap_or(server);
+    let mut address = dns::resolve_server(server)?;
+    address.set_port(port);
+    Ok((host, address))
+}
+
+/// Sends one DoH query for the probe name and checks that a DNS message came
+/// back.
+fn doh_exchange(stream: &mut TlsStream, host: &str, settings: &ProbeSettings) -> io::Result<()> {
+    doh_query(stream, host, &settings.probe_name, settings.record_type).map(drop)
+}
+
+/// Sends one DoH query for `name` and returns the DNS message that came
+/// back.
+fn doh_query(
+    stream: &mut TlsStream,
+    host: &str,
+    name: &str,
+    record_type: u16,
+) -> io::Result<Vec<u8>> {
+    // RFC 8484 asks for id 0 so responses stay cacheable
+    let query = dns::build_query(0, name, record_type);
+    let authority = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    let head = format!(
+        "POST /dns-query HTTP/1.1\r\nHost: {}\r\nContent-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+        authority,
+        query.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&query)?;
+    stream.flush()?;
+
+    let (status, body) = read_http_response(stream)?;
+    if status != 200 {
+        return Err(io::Error::other(format!("HTTP status {}", status)));
+    }
+    if body.len() < 12 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "malformed DNS response",
+        ));
+    }
+    Ok(body)
+}
+
+/// Reads an HTTP/1.1 response with a `Content-Length` body, leaving the
+/// connection ready for the next request.
+fn read_http_response(stream: &mut impl Read) -> io::Result<(u16, Vec<u8>)> {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(invalid)?;
+    let length: usize = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .ok_or_else(invalid)?;
+
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body)?;
+    Ok((status, body))
+}
+
+/// Runs `probe` for the warmup and measured rounds, counting failures as
+/// lost probes.
+///
+/// A probe that timed out is retried up to `settings.timeout_retries` times
+/// and the round-trip time of a retry that succeeds is used instead. Probes,
+/// warmups and retries included, start at least `settings.probe_interval`
+/// apart.
+fn repeat(
+    backend: &dyn MeasurementBackend,
+    server: &str,
+    settings: &ProbeSettings,
+    mut send: impl FnMut() -> io::Result<Duration>,
+) -> Measurement {
+    let mut last_start: Option<Instant> = None;
+    let mut probe = || {
+        if let Some(last_start) = last_start {
+            thread::sleep(settings.probe_interval.saturating_sub(last_start.elapsed()));
+        }
+        last_start = Some(Instant::now());
+        send()
+    };
+    for _ in 0..settings.warmup {
+        let _ = probe();
+    }
+    let mut samples = Vec::new();
+    let mut last_error = None;
+    for _ in 0..settings.count {
+        let mut result = probe();
+        for _ in 0..settings.timeout_retries {
+            match &result {
+                Err(err) if is_timeout(err) => result = probe(),
+                _ => break,
+            }
+        }
+        match result {
+            Ok(elapsed) => {
+                if settings.log_pop {
+                    log_pop(backend, server, samples.len() + 1, elapsed, settings);
+                }
+                samples.push(elapsed);
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    if let (true, Some(err)) = (samples.is_empty(), last_error) {
+        warn(&format!(
+            "{} probes to {} failed: {}",
+            backend.name(),
+            server,
+            err
+        ));
+    }
+    Measurement {
+        samples,
+        sent: settings.count,
+        cold: None,
+        setup: None,
+        ttl: None,
+        minimizes: None,
+    }
+}
+
+/// Logs a probe's latency with the instance of the server that answered
+/// it, as the server names itself right after the probe.
+fn log_pop(
+    backend: &dyn MeasurementBackend,
+    server: &str,
+    probe: usize,
+    elapsed: Duration,
+    settings: &ProbeSettings,
+) {
+    log(&format!(
+        "{} probe {} to {}: {:.2?} from {}",
+        backend.name(),
+        probe,
+        server,
+        elapsed,
+        pop::identify(server, settings.timeout)
+            .as_deref()
+            .unwrap_or("unknown instance")
+    ));
+}
+
+/// Whether a probe failed because no answer arrived in time; sockets report
+/// a read timeout as `WouldBlock` on some platforms.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+/// Like [`repeat`] for connection-oriented transports: only the exchange
+/// is timed, and a connection that failed is reopened for the next probe.
+/// Opening connections is timed on its own, as the setup time. An already
+/// open `connection` is used first, with the time it took to open.
+fn repeat_on_connection<C>(
+    backend: &dyn MeasurementBackend,
+    server: &str,
+    settings: &ProbeSettings,
+    connection: Option<(C, Duration)>,
+    mut connect: impl FnMut() -> io::Result<C>,
+    mut exchange: impl FnMut(&mut C) -> io::Result<()>,
+) -> Measurement {
+    let mut setups: Vec<Duration> = connection.iter().map(|(_, setup)| *setup).collect();
+    let mut connection = connection.map(|(stream, _)| stream);
+    let measurement = repeat(backend, server, settings, || {
+        let stream = match &mut connection {
+            Some(stream) => stream,
+            None => {
+                let start = Instant::now();
+                let stream = connect()?;
+                setups.push(start.elapsed());
+                connection.insert(stream)
+            }
+        };
+        let start = Instant::now();
+        let result = exchange(stream);
+        if result.is_err() {
+            connection = None;
+        }
+        result.map(|()| start.elapsed())
+    });
+    Measurement {
+        setup: (!setups.is_empty()).then(|| setups.iter().sum::<Duration>() / setups.len() as u32),
+        ..measurement
+    }
+}
+
+/// A measurement of a server that could not be probed at all.
+fn unreachable(
+    backend: &dyn MeasurementBackend,
+    server: &str,
+    settings: &ProbeSettings,
+    err: io::Error,
+) -> Measurement {
+    warn(&format!(
+        "cannot probe {} over {}: {}",
+        server,
+        backend.name(),
+        err
+    ));
+    Measurement {
+        samples: Vec::new(),
+        sent: settings.count,
+        cold: None,
+        setup: None,
+        ttl: None,
+        minimizes: None,
+    }
+}
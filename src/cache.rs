@@ -0,0 +1,88 @@
+//! Estimating how often a resolver answers from its cache over a `--watch`
+//! session, for `--cache-stats`.
+//!
+//! Every cycle each server is asked for the probe domain a few times and
+//! once for a random name it cannot have cached. The fastest answer seen is
+//! the warm baseline (network round trip plus a cache hit), the random name
+//! the cold one (a full recursion); an answer closer to the warm baseline
+//! counts as a hit.
+
+use crate::{dns, hijack, measurement::Measurement, providers::Provider};
+use fastrand::Rng;
+use std::{collections::HashMap, time::Duration};
+
+/// Queries for the probe domain sent to each server per cycle.
+const QUERIES_PER_CYCLE: usize = 3;
+
+/// Cache behavior of one server so far.
+#[derive(Default)]
+pub struct Tally {
+    /// Fastest answer for the probe domain seen this session
+    pub warm: Option<Duration>,
+    /// Latest answer for a name that cannot be cached
+    pub cold: Option<Duration>,
+    /// Probe domain answers classified as hits
+    pub hits: usize,
+    /// Probe domain answers classified at all
+    pub total: usize,
+}
+
+impl Tally {
+    /// Fraction of answers that came from the cache, between 0.0 and 1.0.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.hits as f64 / self.total as f64)
+    }
+}
+
+/// Tallies of every server seen this session, keyed by address.
+#[derive(Default)]
+pub struct CacheStats {
+    tallies: HashMap<String, Tally>,
+}
+
+impl CacheStats {
+    /// Probes every server that answered this cycle and classifies the
+    /// answers. Answers are only classified once the cold baseline is clearly
+    /// slower than the warm one, otherwise the two cannot be told apart.
+    pub fn update(
+        &mut self,
+        results: &[(&Provider, Measurement)],
+        name: &str,
+        timeout: Duration,
+        rng: &mut Rng,
+    ) {
+        for (provider, measurement) in results {
+            if !measurement.reachable() {
+                continue;
+            }
+            let Ok(server) = dns::resolve_server(&provider.ip) else {
+                continue;
+            };
+            let answers: Vec<Duration> = (0..QUERIES_PER_CYCLE)
+                .filter_map(|_| dns::query(server, name, dns::TYPE_A, timeout).ok())
+                .collect();
+            let tally = self.tallies.entry(provider.ip.clone()).or_default();
+            if let Ok(cold) = dns::query(server, &hijack::random_name(rng), dns::TYPE_A, timeout) {
+                tally.cold = Some(cold);
+            }
+            tally.warm = answers.iter().copied().chain(tally.warm).min();
+            let (Some(warm), Some(cold)) = (tally.warm, tally.cold) else {
+                continue;
+            };
+            if cold <= warm * 2 {
+                continue;
+            }
+            let threshold = warm + (cold - warm) / 2;
+            tally.hits += answers
+                .iter()
+                .filter(|&&answer| answer <= threshold)
+                .count();
+            tally.total += answers.len();
+        }
+    }
+
+    /// The tally of the server at `ip`, if it was ever probed.
+    pub fn tally(&self, ip: &str) -> Option<&Tally> {
+        self.tallies.get(ip)
+    }
+}
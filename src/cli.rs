@@ -0,0 +1,751 @@
+//! Command-line interface definition.
+
+use crate::{
+    dns::{self, PROBE_NAME},
+    health::{self, Weights},
+    history,
+    mock::{self, MockLatencies},
+    providers::{is_valid_hostname, is_valid_nextdns_profile},
+    proxy::{self, Proxy},
+    schedule::{parse_window, Window},
+    score::{self, ScoreExpr},
+    system,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{net::IpAddr, path::PathBuf};
+
+/// Finds the fastest DNS server and sets it up automatically.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log extra detail about the run
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Show each provider's description as a trailing column in the results
+    #[arg(long, global = true)]
+    pub describe: bool,
+
+    /// Read servers to test from a file ("-" for stdin) instead of the
+    /// built-in list, one `<address> [name]` per line
+    #[arg(long, global = true, value_name = "FILE")]
+    pub providers: Option<PathBuf>,
+
+    /// Fetch the servers to test from a JSON or TOML list over HTTPS, falling
+    /// back to the last cached copy if the fetch fails
+    #[arg(long, global = true, value_name = "URL", conflicts_with = "providers", value_parser = parse_https_url)]
+    pub providers_url: Option<String>,
+
+    /// Test your NextDNS profile (e.g. abc123) in place of the shared NextDNS
+    /// address; the ID is saved in the Keychain and used by later runs
+    #[arg(long, global = true, value_name = "ID", value_parser = parse_nextdns_profile)]
+    pub nextdns_profile: Option<String>,
+
+    /// Benchmark the resolvers serving this scope (e.g. a VPN search domain)
+    /// instead of optimizing the system-wide DNS
+    #[arg(long, value_name = "DOMAIN")]
+    pub scope: Option<String>,
+
+    /// Measure these servers head to head with more pings each and report the
+    /// winner with a confidence note, without changing anything
+    #[arg(long, value_name = "ADDRESS", num_args = 2.., conflicts_with = "scope")]
+    pub compare: Vec<String>,
+
+    /// Measure only the currently configured DNS servers, with more pings
+    /// each, and report latency, jitter and loss without changing anything
+    #[arg(long, conflicts_with_all = ["scope", "compare"])]
+    pub current_only: bool,
+
+    /// Only measure, then print `WISP_FASTEST_DNS=<address>` for
+    /// `eval "$(wisp --eval)"`, leaving the system DNS alone
+    #[arg(long, conflicts_with_all = ["scope", "compare", "current_only", "watch", "format"])]
+    pub eval: bool,
+
+    /// Keep running and re-optimize every this many minutes
+    #[arg(long, value_name = "MINUTES", num_args = 0..=1, default_missing_value = "30", conflicts_with_all = ["scope", "compare", "current_only"], value_parser = clap::value_parser!(u64).range(1..))]
+    pub watch: Option<u64>,
+
+    /// With --watch, only change the DNS between these local times, e.g.
+    /// `22:00-06:00`; outside the window the best server is only logged
+    #[arg(long, value_name = "HH:MM-HH:MM", requires = "watch", value_parser = parse_window)]
+    pub window: Option<Window>,
+
+    /// With --watch, rank on a moving average of each server's latency that
+    /// weighs the newest cycle this much (0-1], and ping each server only
+    /// once in the cycles after the first
+    #[arg(long, value_name = "ALPHA", requires = "watch", value_parser = parse_smoothing)]
+    pub smoothing: Option<f64>,
+
+    /// With --watch, estimate how often each server answers from its cache
+    /// over the session and log the hit ratio after every cycle
+    #[arg(long, requires = "watch")]
+    pub cache_stats: bool,
+
+    /// Also write the results as a self-contained HTML report to this file
+    #[arg(long, value_name = "PATH", global = true)]
+    pub html: Option<PathBuf>,
+
+    /// Write everything the run measured and decided, from the raw probe
+    /// timings to the apply outcome, as one JSON document to this file; with
+    /// --watch, the last cycle's decision
+    #[arg(long, value_name = "PATH", global = true)]
+    pub trace_json: Option<PathBuf>,
+
+    /// Label this run in the history log, for `wisp compare`
+    #[arg(long, value_name = "LABEL", global = true)]
+    pub tag: Option<String>,
+
+    /// How many runs the history log keeps, the oldest are dropped
+    #[arg(long, value_name = "RUNS", global = true, default_value_t = history::DEFAULT_HISTORY_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub history_limit: usize,
+
+    /// Send queries for a domain to its own servers (split DNS), e.g.
+    /// `corp.example=10.0.0.53,10.0.0.54`; can be repeated
+    #[arg(long, value_name = "DOMAIN=SERVERS", value_parser = parse_split_dns)]
+    pub split_dns: Vec<SplitDns>,
+
+    /// Remove a domain's split DNS servers set with --split-dns; can be
+    /// repeated
+    #[arg(long, value_name = "DOMAIN")]
+    pub remove_split_dns: Vec<String>,
+
+    /// Explain why the chosen server won: its margin over the runner-up and
+    /// the current DNS, and whether --sticky decided it
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Post a macOS notification summarizing the switch when DNS changes
+    #[arg(long)]
+    pub notify: bool,
+
+    /// Lookups a day that reach the resolver, past the local cache, to
+    /// estimate the time a switch saves per day and month
+    #[arg(long, value_name = "N", default_value_t = 3000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub queries_per_day: u64,
+
+    /// Restore the original DNS configuration if any step after the reset
+    /// fails
+    #[arg(long)]
+    pub safe: bool,
+
+    /// After applying, also read the servers back from the configuration
+    /// saved on disk, which is what survives a reboot, and fail if they are
+    /// missing there (with --apply-with networksetup)
+    #[arg(long)]
+    pub verify_persistent: bool,
+
+    /// Prefer servers answering from a POP in this region, an airport or city
+    /// code like `fra`, over up to 5ms faster ones elsewhere
+    #[arg(long, value_name = "CODE")]
+    pub region: Option<String>,
+
+    /// Apply the chosen server without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// What to do when there is no terminal to ask for confirmation on
+    #[arg(long, value_enum, default_value_t = Unattended::Apply)]
+    pub unattended: Unattended,
+
+    /// Apply the chosen server in front of the current ones instead of
+    /// replacing them, keeping e.g. a corporate resolver as fallback
+    #[arg(long, conflicts_with = "apply_with")]
+    pub prepend: bool,
+
+    /// Print the commands that would apply the chosen servers instead of
+    /// running them; nothing gets changed
+    #[arg(long, conflicts_with_all = ["apply_with", "watch", "eval"])]
+    pub print_commands: bool,
+
+    /// How the chosen servers get applied
+    #[arg(long, value_enum, default_value_t = ApplyWith::Networksetup, conflicts_with = "watch")]
+    pub apply_with: ApplyWith,
+
+    /// Also measure the current DNS servers and leave the configuration
+    /// untouched when one of them wins
+    #[arg(long)]
+    pub keep_if_best: bool,
+
+    /// Number of DNS servers to apply, fastest first
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub servers: u8,
+
+    /// Take the secondary server from a different provider family than the
+    /// primary, so one provider's outage cannot take out both (the default)
+    #[arg(long, overrides_with = "no_diverse")]
+    pub diverse: bool,
+
+    /// Take the second fastest server as secondary even if it belongs to the
+    /// same provider as the primary
+    #[arg(long)]
+    pub no_diverse: bool,
+
+    /// How to choose the server to apply from the results
+    #[arg(long, value_enum, default_value_t = Strategy::Fastest)]
+    pub strategy: Strategy,
+
+    /// Favor the currently configured DNS servers when ranking, so the tool
+    /// only switches when an alternative is clearly better (same as
+    /// `--strategy sticky`)
+    #[arg(long, conflicts_with = "strategy")]
+    pub sticky: bool,
+
+    /// Rank by this latency percentile (e.g. 95) instead of the mean, for
+    /// when the slow tail matters more than the average
+    #[arg(long, value_name = "P", conflicts_with_all = ["strategy", "sticky"], value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub rank_percentile: Option<u8>,
+
+    /// Never apply a server with fewer answered probes than this, however
+    /// fast the few answers were
+    #[arg(long, global = true, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub min_samples: Option<usize>,
+
+    /// Rank by a formula over latency, jitter (both in ms), loss (0 to 1)
+    /// and ttl, lowest first, e.g. "latency + 2*jitter + 100*loss"
+    #[arg(long, value_name = "EXPR", conflicts_with_all = ["strategy", "sticky", "rank_percentile"], value_parser = score::parse)]
+    pub score_expr: Option<ScoreExpr>,
+
+    /// Latency bonus in milliseconds given to the current servers with --sticky
+    #[arg(long, value_name = "MS", default_value_t = 10)]
+    pub sticky_bonus: u64,
+
+    /// Provider name or family preferred by `--strategy prefer-named`,
+    /// comma separated
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_delimiter = ',',
+        required_if_eq("strategy", "prefer-named")
+    )]
+    pub prefer: Vec<String>,
+
+    /// Also measure the local resolver at 127.0.0.1 as a baseline, timed with
+    /// real DNS queries; it is never applied
+    #[arg(long, global = true)]
+    pub include_local: bool,
+
+    /// Also measure the DNS servers DHCP hands out as a baseline, to see
+    /// whether leaving DNS on automatic would be just as fast
+    #[arg(long, global = true)]
+    pub compare_to_automatic: bool,
+
+    /// Also time lookups through the operating system's resolver
+    /// (getaddrinfo) as a baseline, with its caching and everything else
+    /// applications go through
+    #[arg(long, global = true)]
+    pub compare_to_system: bool,
+
+    /// Test only the primary server of each provider family for a faster run
+    #[arg(long, global = true)]
+    pub quick: bool,
+
+    /// Test servers one at a time in list order and stop at the first one
+    /// answering within --threshold, applying it without testing the rest
+    #[arg(long, requires = "threshold")]
+    pub fail_fast: bool,
+
+    /// Latency in milliseconds that is good enough for --fail-fast
+    #[arg(long, value_name = "MS", requires = "fail_fast")]
+    pub threshold: Option<u64>,
+
+    /// Test at most this many servers; combined with --shuffle this is a
+    /// random sample of a large list
+    #[arg(long, global = true, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_providers: Option<usize>,
+
+    /// Check whether each server blocks a known ad/tracker domain and report
+    /// which ones filter
+    #[arg(long, global = true)]
+    pub check_filtering: bool,
+
+    /// Check whether each server minimizes the query names it sends
+    /// upstream (QNAME minimization) and show it in a privacy column
+    #[arg(long, global = true)]
+    pub check_qname_minimization: bool,
+
+    /// Resolve the probe domain on every server and flag the ones whose
+    /// addresses diverge from what most servers return
+    #[arg(long, global = true)]
+    pub check_consistency: bool,
+
+    /// Ask every server for a response too large for a plain UDP datagram
+    /// and report whether it came over UDP with EDNS, over TCP after
+    /// truncation, or not at all
+    #[arg(long, global = true)]
+    pub check_large_responses: bool,
+
+    /// Allow applying servers that fail the correctness checks: answering
+    /// nonexistent domains with an address (NXDOMAIN hijacking) or not
+    /// resolving the probe domain. They are flagged and skipped by default
+    #[arg(long, alias = "allow-hijacking")]
+    pub allow_incorrect: bool,
+
+    /// Test the servers in random order so none gets a consistent positional
+    /// advantage on a busy link
+    #[arg(long, global = true)]
+    pub shuffle: bool,
+
+    /// Probe in this many round-robin passes, each server getting one probe
+    /// per pass in a freshly shuffled order, so a moment of congestion is
+    /// spread over everyone instead of hitting whoever was being tested
+    #[arg(long, global = true, value_name = "PASSES", conflicts_with = "samples", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub interleave: Option<usize>,
+
+    /// Seed of every random choice (--shuffle and --interleave order, random
+    /// probe names), to repeat a run [default: random, and logged]
+    #[arg(long, global = true, value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// How to measure latency, comma separated; the first method drives the
+    /// ranking and the others are logged for comparison
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Method::Icmp]
+    )]
+    pub method: Vec<Method>,
+
+    /// Transports to try in order, comma separated (e.g. doh,dot,udp); each
+    /// server is ranked on the first one it answers over, like a client
+    /// falling back
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "METHODS",
+        conflicts_with = "method"
+    )]
+    pub transport: Vec<Method>,
+
+    /// Domain looked up by the query-based methods (udp, tcp, dot, doh);
+    /// internationalized names are sent as punycode
+    #[arg(long, global = true, value_name = "DOMAIN", default_value = PROBE_NAME, value_parser = parse_probe_domain)]
+    pub probe_domain: String,
+
+    /// Record type the query-based methods ask for
+    #[arg(long, global = true, value_enum, default_value_t = RecordType::A)]
+    pub record_type: RecordType,
+
+    /// Time lookups of each of these record types in one pass, e.g.
+    /// `a,aaaa,https`, and rank on their combined latency
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "TYPES",
+        value_delimiter = ',',
+        conflicts_with = "record_type"
+    )]
+    pub record_types: Vec<RecordType>,
+
+    /// How long to wait for each probe in milliseconds [default: 2000, or
+    /// 1000 when not run from a terminal]
+    #[arg(long, global = true, value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// Connect the dot and doh probes through this proxy,
+    /// `http://HOST:PORT` (CONNECT) or `socks5://HOST:PORT`
+    #[arg(long, global = true, value_name = "URL", value_parser = proxy::parse)]
+    pub proxy: Option<Proxy>,
+
+    /// Send every probe from this local address, or from the address of
+    /// this interface (e.g. `utun3` for a VPN), to compare network paths
+    #[arg(long, global = true, value_name = "INTERFACE|IP", value_parser = parse_source)]
+    pub source: Option<IpAddr>,
+
+    /// Send every probe through this VRF device, so it follows that routing
+    /// table (Linux only)
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "source", value_parser = parse_vrf)]
+    pub vrf: Option<String>,
+
+    /// Retry a timed-out probe up to this many times before counting it as
+    /// lost, for flaky links
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    pub timeout_retries: u32,
+
+    /// Least time between two probes to the same server in milliseconds,
+    /// so resolvers that rate-limit bursts are measured at a normal pace
+    #[arg(long, global = true, value_name = "MS", default_value_t = 10)]
+    pub probe_interval: u64,
+
+    /// How long to wait after changing DNS before checking it in
+    /// milliseconds [default: 2000, or 500 when not run from a terminal]
+    #[arg(long, global = true, value_name = "MS")]
+    pub settle: Option<u64>,
+
+    /// How many more times to read applied servers back when they did not
+    /// stick yet, before restoring the previous ones
+    #[arg(long, global = true, value_name = "N", default_value_t = system::DEFAULT_VERIFY_RETRIES)]
+    pub verify_retries: u32,
+
+    /// Measure at most this many servers at the same time
+    #[arg(long, global = true, value_name = "N", default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_concurrent: usize,
+
+    /// Number of measured probes per server [default: 3, or 20 with
+    /// --rank-percentile, enough for a meaningful tail]
+    #[arg(long, global = true, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub samples: Option<usize>,
+
+    /// Also ping this nearby host (the router, say) as the latency floor of
+    /// the link, shown with the results so a slow link can be told apart
+    /// from slow servers
+    #[arg(long, global = true, value_name = "HOST")]
+    pub ping_baseline: Option<String>,
+
+    /// Number of discarded pings sent to each server before measuring it
+    #[arg(long, global = true, value_name = "N", default_value_t = 1)]
+    pub warmup: u32,
+
+    /// Skip the discarded probe sent before the first server is measured,
+    /// which warms up routes and caches so the servers tested first are not
+    /// penalized
+    #[arg(long, global = true)]
+    pub no_global_warmup: bool,
+
+    /// Debugging aid: log the full output of every ping next to what was
+    /// parsed from it
+    #[arg(long, global = true, hide = true)]
+    pub raw_ping_output: bool,
+
+    /// Testing aid: take every server's probe times from this file instead
+    /// of the network, one `<address> <ms> [<ms> ...]` line per server with
+    /// `-` for a lost probe
+    #[arg(long, global = true, hide = true, value_name = "FILE", value_parser = mock::load)]
+    pub mock_latencies: Option<MockLatencies>,
+
+    /// Debugging aid: log which instance of an anycast server (`id.server`
+    /// or `hostname.bind`) answered next to every probe's latency
+    #[arg(long, global = true)]
+    pub log_pop: bool,
+
+    /// Columns of the results table, comma separated
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Column::Name, Column::Ip, Column::Latency]
+    )]
+    pub columns: Vec<Column>,
+
+    /// Grade every server from 0 to 100 (A-F) on all the signals the run
+    /// measured; --explain shows what the winner's grade is made of
+    #[arg(long, global = true)]
+    pub health: bool,
+
+    /// How much each signal counts in --health, e.g. latency=50,loss=30
+    /// [default: latency=40,jitter=15,loss=25,cold=5,correctness=10,large=5]
+    #[arg(long, global = true, value_name = "WEIGHTS", requires = "health", value_parser = health::parse_weights)]
+    pub health_weights: Option<Weights>,
+
+    /// Output format for the results
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    pub format: Format,
+
+    /// Unit latencies are reported in, in the table, JSON and CSV alike
+    #[arg(long, global = true, value_enum, default_value_t = Unit::Ms)]
+    pub unit: Unit,
+
+    /// Fractional digits of reported latencies [default: 2 for ms and 0 for
+    /// us in the table, unrounded in JSON and CSV]
+    #[arg(long, global = true, value_name = "N")]
+    pub precision: Option<usize>,
+
+    /// Format of the diagnostic log written to stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Human)]
+    pub log_format: LogFormat,
+
+    /// Never run alongside another --once run, for tight cron/launchd
+    /// schedules: exit right away while one is in progress, or wait for it
+    #[arg(long, global = true, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "exit")]
+    pub once: Option<Once>,
+}
+
+/// Servers that answer for one domain and its subdomains.
+#[derive(Clone)]
+pub struct SplitDns {
+    pub domain: String,
+    pub servers: Vec<String>,
+}
+
+/// Accepts only `https://` URLs, so provider lists cannot be tampered with
+/// in transit.
+fn parse_https_url(value: &str) -> Result<String, String> {
+    if value.starts_with("https://") {
+        Ok(value.to_string())
+    } else {
+        Err("only https:// URLs are supported".to_string())
+    }
+}
+
+/// Accepts a local IP address, or an interface whose address is used.
+fn parse_source(value: &str) -> Result<IpAddr, String> {
+    value
+        .parse()
+        .or_else(|_| system::interface_address(value).map_err(|err| err.to_string()))
+}
+
+/// Accepts the name of an existing network device for `--vrf`, on Linux.
+fn parse_vrf(value: &str) -> Result<String, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("VRFs are only supported on Linux".to_string());
+    }
+    let name = std::ffi::CString::new(value).map_err(|_| format!("invalid device {:?}", value))?;
+    // SAFETY: `name` is a NUL-terminated string that outlives the call
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(format!("no network device named {:?}", value)),
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// Accepts a smoothing factor above 0 and at most 1.
+fn parse_smoothing(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(alpha),
+        _ => Err(format!("{:?} is not a number above 0 and at most 1", value)),
+    }
+}
+
+/// Accepts a NextDNS profile ID, lowercased as NextDNS shows it.
+fn parse_nextdns_profile(value: &str) -> Result<String, String> {
+    let profile = value.trim().to_ascii_lowercase();
+    if is_valid_nextdns_profile(&profile) {
+        Ok(profile)
+    } else {
+        Err(format!(
+            "{:?} is not a NextDNS profile ID (six hex digits, e.g. abc123)",
+            value
+        ))
+    }
+}
+
+/// Accepts a probe domain, including internationalized names that have a
+/// valid punycode form.
+fn parse_probe_domain(value: &str) -> Result<String, String> {
+    match idna::domain_to_ascii(value) {
+        Ok(ascii) if is_valid_hostname(&ascii) => Ok(value.to_string()),
+        _ => Err(format!("invalid domain {:?}", value)),
+    }
+}
+
+/// Parses a `DOMAIN=SERVER[,SERVER...]` split DNS entry.
+fn parse_split_dns(value: &str) -> Result<SplitDns, String> {
+    let (domain, servers) = value
+        .split_once('=')
+        .ok_or("expected DOMAIN=SERVER[,SERVER...]")?;
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    if !is_valid_hostname(&domain) {
+        return Err(format!("invalid domain {:?}", domain));
+    }
+    let servers: Vec<String> = servers
+        .split(',')
+        .map(|server| server.trim().to_string())
+        .collect();
+    if let Some(server) = servers
+        .iter()
+        .find(|server| server.parse::<IpAddr>().is_err())
+    {
+        return Err(format!("{:?} is not an IP address", server));
+    }
+    Ok(SplitDns { domain, servers })
+}
+
+/// How log events are written to stderr.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Timestamped text lines
+    Human,
+    /// One JSON object per line with `ts`, `level` and `message`
+    Json,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Benchmark servers and print a ranked table without reading or
+    /// changing the system DNS configuration
+    Bench,
+    /// Check that the tools, network and permissions wisp needs are in place
+    Doctor,
+    /// Set the DNS to a provider unless it is already in use, without any
+    /// latency test, and print "changed" or "unchanged"
+    Ensure {
+        /// Server name, provider family (every server of it) or IP address
+        #[arg(long, value_name = "NAME|IP")]
+        provider: String,
+    },
+    /// Print per-server latency changes between the latest runs tagged with
+    /// --tag <BEFORE> and --tag <AFTER>
+    Compare { before: String, after: String },
+    /// Print the servers a run would test once the provider list, --quick,
+    /// --max-providers and the other selection options are applied, without
+    /// probing any of them
+    Providers,
+}
+
+/// Where the chosen servers get configured.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ApplyWith {
+    /// The Wi-Fi service's DNS servers, through `networksetup`
+    Networksetup,
+    /// A DNS over TLS configuration profile, approved in System Settings or
+    /// deployed through MDM, for machines whose policy reverts networksetup
+    Profile,
+}
+
+/// What a run that cannot ask for confirmation does.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Unattended {
+    /// Apply the chosen server, as with --yes
+    Apply,
+    /// Only report the chosen server and keep the original configuration
+    DryRun,
+}
+
+/// What `--once` does while another run is in progress.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Once {
+    /// Exit with status 4 without doing anything
+    Exit,
+    /// Wait for the other run to finish, then go ahead
+    Wait,
+}
+
+/// A way of measuring a server's latency.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Method {
+    /// ICMP echo through the system `ping`
+    Icmp,
+    /// DNS queries over UDP
+    Udp,
+    /// DNS queries over TCP
+    Tcp,
+    /// DNS over TLS on port 853
+    Dot,
+    /// DNS over HTTPS on port 443
+    Doh,
+}
+
+/// How the server to apply is chosen from the results.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Strategy {
+    /// Lowest mean latency
+    Fastest,
+    /// Fewest lost probes, ties broken by latency
+    LowestLoss,
+    /// Lowest latency plus jitter plus a penalty for loss
+    Composite,
+    /// Lowest latency after a --sticky-bonus for the current servers
+    Sticky,
+    /// A --prefer server if any answered, otherwise the fastest
+    PreferNamed,
+}
+
+/// A DNS record type to time lookups of.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RecordType {
+    /// IPv4 addresses
+    A,
+    /// IPv6 addresses
+    Aaaa,
+    /// Mail servers
+    Mx,
+    /// Text records
+    Txt,
+    /// Service binding (SVCB)
+    Svcb,
+    /// HTTPS service binding, queried by modern browsers
+    Https,
+}
+
+impl RecordType {
+    /// The type code sent in queries.
+    pub fn code(self) -> u16 {
+        match self {
+            RecordType::A => dns::TYPE_A,
+            RecordType::Aaaa => dns::TYPE_AAAA,
+            RecordType::Mx => dns::TYPE_MX,
+            RecordType::Txt => dns::TYPE_TXT,
+            RecordType::Svcb => dns::TYPE_SVCB,
+            RecordType::Https => dns::TYPE_HTTPS,
+        }
+    }
+
+    /// The type as written in zone files, e.g. `AAAA`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+            RecordType::Svcb => "SVCB",
+            RecordType::Https => "HTTPS",
+        }
+    }
+}
+
+/// A column of the human results table.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Column {
+    /// Provider name
+    Name,
+    /// Server address
+    Ip,
+    /// Mean round-trip time
+    Latency,
+    /// Mean round-trip time as a bare whole number of milliseconds, for
+    /// `sort -n` and awk
+    Ms,
+    /// Round-trip time including connection setup, shown for DoH
+    Cold,
+    /// Time to open a connection (TCP connect, TLS handshake), which the
+    /// latency leaves out; empty for udp and icmp
+    Setup,
+    /// Whether the server minimizes query names, with
+    /// --check-qname-minimization
+    Privacy,
+    /// Median round-trip time
+    P50,
+    /// Round-trip time 90% of the probes were at or under
+    P90,
+    /// Round-trip time 95% of the probes were at or under
+    P95,
+    /// Round-trip time 99% of the probes were at or under
+    P99,
+    /// Mean difference between consecutive round-trip times
+    Jitter,
+    /// Share of lost probes
+    Loss,
+    /// TTL left in the ping replies; higher usually means fewer hops
+    Ttl,
+    /// What the provider is known for, also added by --describe
+    Description,
+}
+
+/// Unit of reported latencies.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Unit {
+    /// Milliseconds with two decimals
+    Ms,
+    /// Whole microseconds
+    Us,
+}
+
+/// How benchmark results are written to stdout.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// Aligned table of the servers that answered
+    Human,
+    /// JSON array including unreachable servers
+    Json,
+    /// CSV with a header row, including unreachable servers
+    Csv,
+    /// GitHub-flavored Markdown table of the servers that answered
+    Markdown,
+}
@@ -0,0 +1,51 @@
+//! Spotting resolvers whose answers diverge from what the other resolvers
+//! return for the same name (split horizon, geo-routing or manipulation).
+
+use crate::dns;
+use std::{net::IpAddr, time::Duration};
+
+/// Looks up the `A` records of `name` on the resolver at `address`, sorted.
+pub fn answers(address: &str, name: &str, timeout: Duration) -> Result<Vec<IpAddr>, String> {
+    let server = dns::resolve_server(address).map_err(|err| err.to_string())?;
+    let response = dns::lookup(server, name, timeout).map_err(|err| err.to_string())?;
+    if response.rcode != 0 {
+        return Err(format!("response code {}", response.rcode));
+    }
+    let mut addresses = response.addresses;
+    addresses.sort();
+    Ok(addresses)
+}
+
+/// The answer given by the most resolvers, the earlier one on a tie; empty
+/// when no resolver answered with addresses.
+pub fn consensus(answers: &[&[IpAddr]]) -> Vec<IpAddr> {
+    answers
+        .iter()
+        .filter(|answer| !answer.is_empty())
+        .max_by_key(|answer| {
+            let votes = answers.iter().filter(|other| other == answer).count();
+            (
+                votes,
+                std::cmp::Reverse(answers.iter().position(|other| other == *answer)),
+            )
+        })
+        .map_or_else(Vec::new, |answer| answer.to_vec())
+}
+
+/// Whether `answer` shares nothing with `consensus`, not even a network:
+/// CDNs hand out different addresses from the same /16 (IPv4) or /32 (IPv6)
+/// depending on the resolver, which is expected rather than suspicious.
+pub fn diverges(answer: &[IpAddr], consensus: &[IpAddr]) -> bool {
+    !answer
+        .iter()
+        .any(|ip| consensus.iter().any(|other| same_network(*ip, *other)))
+}
+
+/// Whether two addresses lie in the same /16 (IPv4) or /32 (IPv6).
+fn same_network(a: IpAddr, b: IpAddr) -> bool {
+    match (a, b) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..2] == b.octets()[..2],
+        (IpAddr::V6(a), IpAddr::V6(b)) => a.octets()[..4] == b.octets()[..4],
+        _ => false,
+    }
+}
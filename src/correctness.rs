@@ -0,0 +1,98 @@
+//! Basic correctness checks a resolver has to pass before it is applied,
+//! however fast it is: honest NXDOMAIN answers (see [`hijack`]) and an
+//! answer for a name that certainly exists.
+
+use crate::{
+    backend::{self, ProbeSettings},
+    dns::Response,
+    hijack,
+};
+use fastrand::Rng;
+use std::fmt;
+
+/// Outcome of [`check`].
+pub enum Verdict {
+    /// Every check passed
+    Correct,
+    /// The resolver answered wrongly, with what it did
+    Incorrect(String),
+    /// The checks could not be performed, with the reason
+    Unknown(String),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Correct => write!(f, "answers correctly"),
+            Verdict::Incorrect(reason) => f.write_str(reason),
+            Verdict::Unknown(reason) => write!(f, "could not be checked ({})", reason),
+        }
+    }
+}
+
+/// Checks the resolver at `address` for NXDOMAIN hijacking, then looks up
+/// `name` (the probe domain) with the record type, port and transport the
+/// resolver is probed with (`settings`, see [`backend::lookup`]).
+///
+/// Only wrong answers count against a resolver; a check that times out or
+/// fails on the way leaves it [`Verdict::Unknown`], since its probes did
+/// get through.
+pub fn check(address: &str, name: &str, settings: &ProbeSettings, rng: &mut Rng) -> Verdict {
+    if let verdict @ hijack::Nxdomain::Hijacked(_) = hijack::check(address, settings, rng) {
+        return Verdict::Incorrect(verdict.to_string());
+    }
+    match backend::lookup(address, name, settings.record_type, settings) {
+        Ok(response) => judge(name, &response),
+        Err(err) => Verdict::Unknown(err.to_string()),
+    }
+}
+
+/// Judges the answer to the lookup of `name`. An error code is wrong for a
+/// name that exists; an empty answer is not, the name may have no records
+/// of the type asked for.
+fn judge(name: &str, response: &Response) -> Verdict {
+    if response.rcode != 0 {
+        Verdict::Incorrect(format!(
+            "fails to resolve {} (response code {})",
+            name, response.rcode
+        ))
+    } else if response.answers == 0 {
+        Verdict::Unknown(format!("no records for {} of the probed type", name))
+    } else {
+        Verdict::Correct
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(rcode: u8, answers: usize) -> Response {
+        Response {
+            rcode,
+            addresses: Vec::new(),
+            texts: Vec::new(),
+            answers,
+        }
+    }
+
+    #[test]
+    fn empty_answer_is_not_incorrect() {
+        let verdict = judge("example.com", &response(0, 0));
+        assert!(matches!(verdict, Verdict::Unknown(_)));
+    }
+
+    #[test]
+    fn error_code_is_incorrect() {
+        let verdict = judge("example.com", &response(3, 0));
+        assert!(matches!(verdict, Verdict::Incorrect(_)));
+    }
+
+    #[test]
+    fn answered_lookup_is_correct() {
+        assert!(matches!(
+            judge("example.com", &response(0, 1)),
+            Verdict::Correct
+        ));
+    }
+}
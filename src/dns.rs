@@ -0,0 +1,387 @@
+//! Minimal DNS client used to time real queries over UDP and streams.
+
+use crate::{hostnames, source};
+use std::{
+    ffi::CString,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, SocketAddrV6},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Port DNS servers listen on.
+pub const DNS_PORT: u16 = 53;
+
+/// Name looked up when timing a server; popular enough to be cached.
+pub const PROBE_NAME: &str = "example.com";
+
+/// The parts of a DNS response wisp looks at.
+#[derive(Debug)]
+pub struct Response {
+    /// Response code, e.g. [`NXDOMAIN`]
+    pub rcode: u8,
+    /// Addresses from the `A` and `AAAA` records of the answer section
+    pub addresses: Vec<IpAddr>,
+    /// Text of the `TXT` records of the answer section
+    pub texts: Vec<String>,
+    /// Number of records in the answer section, of any type
+    pub answers: usize,
+}
+
+/// Record types and classes of the questions wisp asks.
+pub const TYPE_A: u16 = 1;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SVCB: u16 = 64;
+pub const TYPE_HTTPS: u16 = 65;
+pub const TYPE_DNSKEY: u16 = 48;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+const CLASS_CHAOS: u16 = 3;
+
+/// UDP payload size advertised with EDNS, the 2020 DNS flag day value that
+/// avoids IP fragmentation on common paths.
+pub const EDNS_PAYLOAD: u16 = 1232;
+
+/// Largest datagram accepted, so responses above [`EDNS_PAYLOAD`] still fit.
+const MAX_DATAGRAM: usize = 4096;
+
+/// Response code of a name that does not exist.
+pub const NXDOMAIN: u8 = 3;
+
+/// Response code of a query the server refused to answer.
+pub const REFUSED: u8 = 5;
+
+/// Sends one query for the `record_type` records of `name` to `server` and
+/// returns the time until a matching response arrived.
+///
+/// Fails on timeout or when the answer does not belong to the query.
+pub fn query(
+    server: SocketAddr,
+    name: &str,
+    record_type: u16,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    exchange(server, name, record_type, timeout).map(|(elapsed, _)| elapsed)
+}
+
+/// Looks up the `A` records of `name` on `server`.
+pub fn lookup(server: SocketAddr, name: &str, timeout: Duration) -> io::Result<Response> {
+    lookup_record(server, name, TYPE_A, timeout)
+}
+
+/// Looks up the records of type `record_type` of `name` on `server`.
+pub fn lookup_record(
+    server: SocketAddr,
+    name: &str,
+    record_type: u16,
+    timeout: Duration,
+) -> io::Result<Response> {
+    let (_, packet) = exchange(server, name, record_type, timeout)?;
+    parse(&packet)
+}
+
+/// Looks up the `TXT` records of `name` on `server`.
+pub fn lookup_txt(server: SocketAddr, name: &str, timeout: Duration) -> io::Result<Response> {
+    let (_, packet) = exchange(server, name, TYPE_TXT, timeout)?;
+    parse(&packet)
+}
+
+/// Asks `server` for its identity with the `id.server` CHAOS TXT query
+/// (RFC 4892), which anycast operators answer with the name of the
+/// instance that responded, falling back to the older `hostname.bind`.
+/// `None` if the server does not tell.
+pub fn server_identity(server: SocketAddr, timeout: Duration) -> io::Result<Option<String>> {
+    for name in ["id.server", "hostname.bind"] {
+        let id = query_id();
+        let request = build_question(id, name, TYPE_TXT, CLASS_CHAOS);
+        let (_, packet) = exchange_packet(server, id, &request, timeout)?;
+        if let Some(identity) = parse(&packet)?.texts.into_iter().next() {
+            return Ok(Some(identity));
+        }
+    }
+    Ok(None)
+}
+
+/// Parses a response, failing on malformed packets.
+pub fn parse(packet: &[u8]) -> io::Result<Response> {
+    parse_response(packet)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response"))
+}
+
+/// Sends a query and waits for its response, returning the round-trip time
+/// and the raw response.
+fn exchange(
+    server: SocketAddr,
+    name: &str,
+    record_type: u16,
+    timeout: Duration,
+) -> io::Result<(Duration, Vec<u8>)> {
+    let id = query_id();
+    exchange_packet(server, id, &build_query(id, name, record_type), timeout)
+}
+
+/// Sends the encoded query `request` with id `id` over UDP and waits for the
+/// matching response.
+pub fn exchange_packet(
+    server: SocketAddr,
+    id: u16,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<(Duration, Vec<u8>)> {
+    let socket = source::udp_socket(server.ip())?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(server)?;
+
+    let start = Instant::now();
+    socket.send(request)?;
+
+    let mut buffer = [0; MAX_DATAGRAM];
+    loop {
+        let len = socket.recv(&mut buffer)?;
+        // Ignore stray datagrams, e.g. late answers to an earlier query
+        if len >= 12 && buffer[..2] == id.to_be_bytes() && buffer[2] & 0x80 != 0 {
+            return Ok((start.elapsed(), buffer[..len].to_vec()));
+        }
+        if start.elapsed() >= timeout {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+    }
+}
+
+/// Sends a query over a stream transport (TCP or TLS), where every message
+/// is prefixed with its length, and waits for the matching response.
+pub fn exchange_stream<S: Read + Write>(
+    stream: &mut S,
+    name: &str,
+    record_type: u16,
+) -> io::Result<Vec<u8>> {
+    let id = query_id();
+    exchange_stream_packet(stream, id, &build_query(id, name, record_type))
+}
+
+/// Sends the encoded query `request` with id `id` over a stream transport
+/// and waits for the matching response.
+pub fn exchange_stream_packet<S: Read + Write>(
+    stream: &mut S,
+    id: u16,
+    request: &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut message = (request.len() as u16).to_be_bytes().to_vec();
+    message.extend_from_slice(request);
+    stream.write_all(&message)?;
+    stream.flush()?;
+
+    loop {
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let mut response = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut response)?;
+        if response.len() >= 12 && response[..2] == id.to_be_bytes() {
+            return Ok(response);
+        }
+    }
+}
+
+/// Resolves a server address, which may be a hostname or carry an IPv6
+/// zone, to the socket address of its DNS port. Hostnames are resolved
+/// through [`hostnames::resolve`], which reuses recent answers.
+///
+/// The zone of a scoped address like `fe80::1%en0` (or `fe80::1%4`) becomes
+/// the scope id, without which a link-local server cannot be reached; other
+/// transports keep it by only changing the port.
+pub fn resolve_server(address: &str) -> io::Result<SocketAddr> {
+    let (ip, zone) = match address.split_once('%') {
+        Some((ip, zone)) => (ip, Some(zone)),
+        None => (address, None),
+    };
+    match (ip.parse::<IpAddr>(), zone) {
+        (Ok(IpAddr::V6(ip)), Some(zone)) => Ok(SocketAddr::V6(SocketAddrV6::new(
+            ip,
+            DNS_PORT,
+            0,
+            scope_id(zone)?,
+        ))),
+        (Ok(ip), _) => Ok(SocketAddr::new(ip, DNS_PORT)),
+        (Err(_), _) => {
+            hostnames::resolve(address, DNS_PORT).map(|ip| SocketAddr::new(ip, DNS_PORT))
+        }
+    }
+}
+
+/// The scope id of an IPv6 zone, given as an interface name or index.
+fn scope_id(zone: &str) -> io::Result<u32> {
+    if let Ok(index) = zone.parse() {
+        return Ok(index);
+    }
+    let unknown = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no network interface named {:?}", zone),
+        )
+    };
+    let name = CString::new(zone).map_err(|_| unknown())?;
+    // SAFETY: `name` is a NUL-terminated string that outlives the call
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(unknown()),
+        index => Ok(index),
+    }
+}
+
+/// Encodes a recursive `IN` query for the `record_type` records of `name`.
+///
+/// Internationalized names (`例え.jp`) are converted to their punycode
+/// A-labels (`xn--r8jz45g.jp`) first, since DNS labels are plain ASCII.
+pub fn build_query(id: u16, name: &str, record_type: u16) -> Vec<u8> {
+    let name = idna::domain_to_ascii(name).unwrap_or_else(|_| name.to_string());
+    build_question(id, &name, record_type, CLASS_IN)
+}
+
+/// Encodes a query like [`build_query`] that also carries an EDNS `OPT`
+/// record (RFC 6891) advertising [`EDNS_PAYLOAD`] and asking for DNSSEC
+/// records, which is what makes responses large.
+pub fn build_edns_query(id: u16, name: &str, record_type: u16) -> Vec<u8> {
+    let mut packet = build_query(id, name, record_type);
+    // One additional record
+    packet[11] = 1;
+    // Root owner, OPT type, payload size as class, no extended rcode or
+    // version, the DNSSEC OK flag and no options
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    packet.extend_from_slice(&EDNS_PAYLOAD.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x80, 0, 0, 0]);
+    packet
+}
+
+/// Whether a response has the TC flag set, i.e. did not fit the datagram and
+/// should be asked for again over TCP.
+pub fn is_truncated(packet: &[u8]) -> bool {
+    packet.get(2).is_some_and(|flags| flags & 0x02 != 0)
+}
+
+/// Encodes a recursive query with one question for an ASCII `name`.
+fn build_question(id: u16, name: &str, record_type: u16, class: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(18 + name.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Flags: recursion desired; one question, no other records
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    // The root (".") has no labels at all
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&class.to_be_bytes());
+    packet
+}
+
+/// Decodes the response code and answer addresses of a response.
+///
+/// Returns `None` if the packet is truncated or otherwise malformed.
+fn parse_response(packet: &[u8]) -> Option<Response> {
+    let header = packet.get(..12)?;
+    let rcode = header[3] & 0x0f;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset)? + 4;
+    }
+    let mut addresses = Vec::new();
+    let mut texts = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(packet, offset)?;
+        let fields = packet.get(offset..offset + 10)?;
+        let record_type = u16::from_be_bytes([fields[0], fields[1]]);
+        let length = u16::from_be_bytes([fields[8], fields[9]]) as usize;
+        let data = packet.get(offset + 10..offset + 10 + length)?;
+        match (record_type, length) {
+            (TYPE_A, 4) => addresses.push(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+            (TYPE_AAAA, 16) => addresses.push(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+            (TYPE_TXT, _) => texts.push(parse_txt(data)?),
+            _ => {}
+        }
+        offset += 10 + length;
+    }
+    Some(Response {
+        rcode,
+        addresses,
+        texts,
+        answers: answers as usize,
+    })
+}
+
+/// Joins the length-prefixed strings of a `TXT` record.
+fn parse_txt(mut data: &[u8]) -> Option<String> {
+    let mut text = String::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let part = rest.get(..len as usize)?;
+        text.push_str(&String::from_utf8_lossy(part));
+        data = &rest[len as usize..];
+    }
+    Some(text)
+}
+
+/// Returns the offset just past the (possibly compressed) name at `offset`.
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(offset)?;
+        match len {
+            0 => return Some(offset + 1),
+            // A compression pointer ends the name
+            len if len & 0xc0 == 0xc0 => return Some(offset + 2),
+            len => offset += 1 + len as usize,
+        }
+    }
+}
+
+/// Picks a query id that differs between consecutive queries.
+pub fn query_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    (nanos ^ std::process::id()) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoped_link_local_keeps_its_zone() {
+        let address = resolve_server("fe80::1%3").unwrap();
+        let SocketAddr::V6(scoped) = address else {
+            panic!("{} is not IPv6", address);
+        };
+        assert_eq!(scoped.scope_id(), 3);
+        assert_eq!(address.to_string(), "[fe80::1%3]:53");
+        let spelled = format!("{}%{}", scoped.ip(), scoped.scope_id());
+        assert_eq!(resolve_server(&spelled).unwrap(), address);
+
+        let loopback = if cfg!(target_os = "macos") {
+            "lo0"
+        } else {
+            "lo"
+        };
+        let SocketAddr::V6(named) = resolve_server(&format!("fe80::1%{}", loopback)).unwrap()
+        else {
+            panic!("fe80::1%{} is not IPv6", loopback);
+        };
+        assert_ne!(named.scope_id(), 0);
+        assert!(resolve_server("fe80::1%nosuchif0").is_err());
+    }
+
+    #[test]
+    fn idn_query_uses_a_labels() {
+        let packet = build_query(1, "例え.jp", TYPE_A);
+        let mut expected = vec![11];
+        expected.extend_from_slice(b"xn--r8jz45g");
+        expected.push(2);
+        expected.extend_from_slice(b"jp");
+        expected.push(0);
+        assert_eq!(&packet[12..packet.len() - 4], &expected[..]);
+    }
+}
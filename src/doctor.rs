@@ -0,0 +1,192 @@
+//! Pre-flight checks run by the `doctor` subcommand.
+
+use crate::{
+    error::Error,
+    logging::log,
+    system::{self, SERVICE},
+};
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Commands wisp shells out to.
+const TOOLS: [&str; 4] = ["scutil", "networksetup", "ping", "route"];
+
+/// Command only needed for `--notify`, reported but never failed.
+const NOTIFY_TOOL: &str = "osascript";
+
+/// Apple's captive portal check, which answers "Success" on an open network.
+const CAPTIVE_HOST: &str = "captive.apple.com";
+
+/// How long the captive portal check may take.
+const CAPTIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of one check.
+struct Check {
+    label: String,
+    passed: bool,
+    detail: String,
+}
+
+impl Check {
+    fn new(label: &str, passed: bool, detail: impl Into<String>) -> Self {
+        Check {
+            label: label.to_string(),
+            passed,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Runs every check, prints a pass/fail checklist and fails if any check
+/// did not pass.
+pub fn diagnose() -> Result<(), Error> {
+    log("\nChecking the environment...");
+    let mut checks: Vec<Check> = TOOLS
+        .iter()
+        .map(|tool| match find_on_path(tool) {
+            Some(path) => Check::new(tool, true, path.display().to_string()),
+            None => Check::new(tool, false, "not found on PATH"),
+        })
+        .collect();
+    checks.push(Check::new(
+        NOTIFY_TOOL,
+        true,
+        match find_on_path(NOTIFY_TOOL) {
+            Some(path) => path.display().to_string(),
+            None => "not found, --notify will not work".to_string(),
+        },
+    ));
+    checks.push(check_services());
+    checks.push(check_interface());
+    checks.push(check_privileges());
+    checks.push(check_captive_portal());
+
+    let width = checks
+        .iter()
+        .map(|check| check.label.len())
+        .max()
+        .unwrap_or(0);
+    for check in &checks {
+        println!(
+            "[{}] {:width$}  {}",
+            if check.passed { "pass" } else { "FAIL" },
+            check.label,
+            check.detail,
+            width = width
+        );
+    }
+
+    match checks.iter().filter(|check| !check.passed).count() {
+        0 => {
+            log("\nAll checks passed");
+            Ok(())
+        }
+        failed => Err(Error::Checks { failed }),
+    }
+}
+
+/// Looks up an executable in the directories of `PATH`.
+fn find_on_path(tool: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(tool))
+        .find(|path| path.is_file())
+}
+
+/// Checks that the service whose DNS wisp changes exists.
+fn check_services() -> Check {
+    let label = "network services";
+    match system::list_services() {
+        Ok(services) if services.iter().any(|service| service == SERVICE) => {
+            Check::new(label, true, services.join(", "))
+        }
+        Ok(services) => Check::new(
+            label,
+            false,
+            format!("no {} service, found: {}", SERVICE, services.join(", ")),
+        ),
+        Err(err) => Check::new(label, false, err.to_string()),
+    }
+}
+
+/// Checks that the default route goes through the service wisp changes.
+fn check_interface() -> Check {
+    let label = "active interface";
+    let Some(device) = system::default_interface() else {
+        return Check::new(label, false, "no default route, the machine looks offline");
+    };
+    match system::hardware_port(&device) {
+        Some(port) if port == SERVICE => Check::new(label, true, format!("{} ({})", device, port)),
+        Some(port) => Check::new(
+            label,
+            false,
+            format!(
+                "traffic goes through {} ({}), but wisp changes {}",
+                device, port, SERVICE
+            ),
+        ),
+        None => Check::new(label, false, format!("{} has no hardware port", device)),
+    }
+}
+
+/// Checks that the user may change network settings.
+fn check_privileges() -> Check {
+    let label = "privileges";
+    let id = |flag: &str| {
+        system::run("id", &[flag])
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_default()
+    };
+    if id("-u") == "0" {
+        return Check::new(label, true, "running as root");
+    }
+    if id("-Gn").split_whitespace().any(|group| group == "admin") {
+        Check::new(label, true, "administrator account")
+    } else {
+        Check::new(
+            label,
+            false,
+            "not an administrator, networksetup will refuse changes",
+        )
+    }
+}
+
+/// Checks for a captive portal intercepting plain HTTP.
+fn check_captive_portal() -> Check {
+    let label = "captive portal";
+    match fetch_captive_page() {
+        Ok(body) if body.contains("Success") => Check::new(label, true, "none detected"),
+        Ok(_) => Check::new(
+            label,
+            false,
+            "traffic is intercepted, log in to the network first",
+        ),
+        Err(err) => Check::new(
+            label,
+            false,
+            format!("could not reach {}: {}", CAPTIVE_HOST, err),
+        ),
+    }
+}
+
+/// Fetches Apple's captive portal page over plain HTTP.
+fn fetch_captive_page() -> io::Result<String> {
+    let address = (CAPTIVE_HOST, 80)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+    let mut stream = TcpStream::connect_timeout(&address, CAPTIVE_TIMEOUT)?;
+    stream.set_read_timeout(Some(CAPTIVE_TIMEOUT))?;
+    write!(
+        stream,
+        "GET /hotspot-detect.html HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        CAPTIVE_HOST
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
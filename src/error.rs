@@ -0,0 +1,83 @@
+//! Errors that abort a wisp run.
+
+use std::fmt;
+
+/// A failure that stops the optimization before it completes.
+#[derive(Debug)]
+pub enum Error {
+    /// A system command could not be run or reported a failure
+    Command { command: String, message: String },
+    /// None of the tested DNS servers answered
+    NoResponse,
+    /// The machine has no default route or no server answered at all
+    NoNetwork,
+    /// A provider list could not be fetched and no cached copy was usable
+    Fetch { url: String, message: String },
+    /// `ensure --provider` matched no known server, family or IP address
+    UnknownProvider(String),
+    /// `compare` was given a tag no run in the history has
+    UnknownTag(String),
+    /// Some `doctor` checks did not pass
+    Checks { failed: usize },
+    /// A report file could not be written
+    Write { path: String, message: String },
+    /// Another run holds the `--once` lock
+    AlreadyRunning,
+    /// The default route kept changing while servers were measured, or
+    /// changed before the result was applied
+    NetworkChanged { before: String, after: String },
+    /// The DNS servers read back after applying differ from the ones applied
+    Verification {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Command { command, message } => write!(f, "`{}` failed: {}", command, message),
+            Error::NoResponse => write!(f, "no DNS server responded"),
+            Error::NoNetwork => write!(
+                f,
+                "no network connectivity, DNS configuration left unchanged"
+            ),
+            Error::Fetch { url, message } => write!(f, "could not load {}: {}", url, message),
+            Error::UnknownProvider(name) => write!(
+                f,
+                "{:?} is neither a known server or provider nor an IP address",
+                name
+            ),
+            Error::UnknownTag(tag) => write!(f, "no run in the history is tagged {:?}", tag),
+            Error::Checks { failed } => write!(f, "{} check(s) failed", failed),
+            Error::Write { path, message } => write!(f, "could not write {}: {}", path, message),
+            Error::AlreadyRunning => write!(f, "another wisp run is still in progress (--once)"),
+            Error::NetworkChanged { before, after } => write!(
+                f,
+                "the network changed from {} to {} during the run, not applying results measured on the old one",
+                before, after
+            ),
+            Error::Verification { expected, actual } => write!(
+                f,
+                "DNS servers read back as [{}] instead of [{}]",
+                actual.join(", "),
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+impl Error {
+    /// Process exit status for this error, so scripts can tell being offline
+    /// apart from other failures.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoNetwork => 3,
+            Error::AlreadyRunning => 4,
+            Error::NetworkChanged { .. } => 5,
+            _ => 1,
+        }
+    }
+}
+
+impl std::error::Error for Error {}
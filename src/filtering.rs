@@ -0,0 +1,68 @@
+//! Detection of resolvers that filter ad and tracker domains.
+
+use crate::dns::{self, NXDOMAIN, REFUSED};
+use std::{fmt, time::Duration};
+
+/// Ad/tracker domain blocked by every common filter list.
+pub const BLOCKED_NAME: &str = "doubleclick.net";
+
+/// Whether a resolver answered the blocked domain truthfully.
+pub enum Filtering {
+    /// The domain was blocked, with how the block showed
+    Filtered(String),
+    /// The real answer came back
+    Unfiltered,
+    /// The check could not be performed, with the reason
+    Unknown(String),
+}
+
+impl fmt::Display for Filtering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filtering::Filtered(how) => write!(f, "filters ({})", how),
+            Filtering::Unfiltered => write!(f, "unfiltered"),
+            Filtering::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// Queries [`BLOCKED_NAME`] on the resolver at `address` and classifies the
+/// answer.
+///
+/// NXDOMAIN, a refusal, an empty answer or a sinkhole address (`0.0.0.0`,
+/// loopback) all count as filtering. [`dns::PROBE_NAME`] is looked up first
+/// as a control, so a resolver that answers nothing properly (or a captive
+/// network rewriting DNS) is not mistaken for a filtering one.
+pub fn check(address: &str, timeout: Duration) -> Filtering {
+    let server = match dns::resolve_server(address) {
+        Ok(server) => server,
+        Err(err) => return Filtering::Unknown(err.to_string()),
+    };
+    match dns::lookup(server, dns::PROBE_NAME, timeout) {
+        Ok(control) if control.rcode == 0 && !control.addresses.is_empty() => {}
+        Ok(_) => return Filtering::Unknown(format!("{} did not resolve either", dns::PROBE_NAME)),
+        Err(err) => return Filtering::Unknown(err.to_string()),
+    }
+    let response = match dns::lookup(server, BLOCKED_NAME, timeout) {
+        Ok(response) => response,
+        Err(err) => return Filtering::Unknown(err.to_string()),
+    };
+
+    match response.rcode {
+        NXDOMAIN => return Filtering::Filtered("NXDOMAIN".to_string()),
+        REFUSED => return Filtering::Filtered("refused".to_string()),
+        0 => {}
+        rcode => return Filtering::Unknown(format!("response code {}", rcode)),
+    }
+    if let Some(sinkhole) = response
+        .addresses
+        .iter()
+        .find(|ip| ip.is_unspecified() || ip.is_loopback())
+    {
+        return Filtering::Filtered(format!("sinkhole {}", sinkhole));
+    }
+    if response.addresses.is_empty() {
+        return Filtering::Filtered("empty answer".to_string());
+    }
+    Filtering::Unfiltered
+}
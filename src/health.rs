@@ -0,0 +1,191 @@
+//! A single 0-100 health score and A-F grade per server for `--health`,
+//! folding every signal a run measured (latency, jitter, loss, the DoH cold
+//! start, the correctness checks and large responses) into one number.
+
+use crate::{measurement::Measurement, truncation::Delivery};
+use std::{fmt, time::Duration};
+
+/// A signal the score is made of.
+#[derive(Clone, Copy)]
+enum Signal {
+    Latency,
+    Jitter,
+    Loss,
+    Cold,
+    Correctness,
+    Large,
+}
+
+impl Signal {
+    const ALL: [Signal; 6] = [
+        Signal::Latency,
+        Signal::Jitter,
+        Signal::Loss,
+        Signal::Cold,
+        Signal::Correctness,
+        Signal::Large,
+    ];
+
+    /// Name of the signal in `--health-weights` and the breakdown.
+    fn name(self) -> &'static str {
+        match self {
+            Signal::Latency => "latency",
+            Signal::Jitter => "jitter",
+            Signal::Loss => "loss",
+            Signal::Cold => "cold",
+            Signal::Correctness => "correctness",
+            Signal::Large => "large",
+        }
+    }
+}
+
+/// How much each signal counts, relative to the others.
+#[derive(Clone)]
+pub struct Weights([f64; 6]);
+
+impl Default for Weights {
+    /// Speed and reliability first, then whether the answers are right.
+    fn default() -> Self {
+        Weights([40.0, 15.0, 25.0, 5.0, 10.0, 5.0])
+    }
+}
+
+impl Weights {
+    fn get(&self, signal: Signal) -> f64 {
+        self.0[signal as usize]
+    }
+}
+
+/// Parses `--health-weights`, e.g. `latency=50,loss=30`: signals left out
+/// keep their default weight, `0` leaves a signal out.
+pub fn parse_weights(value: &str) -> Result<Weights, String> {
+    let mut weights = Weights::default();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (name, weight) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected SIGNAL=WEIGHT, got {:?}", entry))?;
+        let signal = Signal::ALL
+            .into_iter()
+            .find(|signal| signal.name() == name.trim())
+            .ok_or_else(|| {
+                format!(
+                    "unknown signal {:?}, expected latency, jitter, loss, cold, correctness or large",
+                    name.trim()
+                )
+            })?;
+        weights.0[signal as usize] = weight
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|weight| weight.is_finite() && *weight >= 0.0)
+            .ok_or_else(|| format!("{:?} is not a weight of 0 or more", weight.trim()))?;
+    }
+    if weights.0.iter().all(|weight| *weight == 0.0) {
+        return Err("at least one signal needs a weight above 0".to_string());
+    }
+    Ok(weights)
+}
+
+/// What was learned about one server, beyond its measurement.
+pub struct Checks<'a> {
+    /// Whether it passed the correctness checks, `None` if not checked
+    pub correct: Option<bool>,
+    /// How it delivered a large response, with `--check-large-responses`
+    pub delivery: Option<&'a Delivery>,
+}
+
+/// A server's score with the signals it was made of.
+pub struct Health {
+    /// Combined score from 0 to 100
+    pub score: u8,
+    /// Every measured signal with its own 0-100 score and its weight
+    parts: Vec<(Signal, u8, f64)>,
+}
+
+impl Health {
+    /// The letter grade: A from 90, B from 80, C from 70, D from 60, else F.
+    pub fn grade(&self) -> char {
+        match self.score {
+            90.. => 'A',
+            80..=89 => 'B',
+            70..=79 => 'C',
+            60..=69 => 'D',
+            _ => 'F',
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    /// The breakdown, e.g. `92 (A): latency 95 x40, loss 100 x25, ...`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .parts
+            .iter()
+            .map(|(signal, score, weight)| format!("{} {} x{}", signal.name(), score, weight))
+            .collect();
+        write!(f, "{} ({}): {}", self.score, self.grade(), parts.join(", "))
+    }
+}
+
+/// Scores a server. Every signal maps to 0-100 on its own: latency is full
+/// marks up to 10ms and none from 200ms, jitter none from 50ms, the cold
+/// start full up to 50ms and none from 500ms, loss by the share answered,
+/// large responses 100 over UDP, 50 over TCP and 0 when lost. Signals the
+/// run did not measure are left out, the others weighted as given.
+pub fn score(measurement: &Measurement, checks: &Checks, weights: &Weights) -> Health {
+    let Some(latency) = measurement.latency() else {
+        return Health {
+            score: 0,
+            parts: Vec::new(),
+        };
+    };
+    let measured = |signal| match signal {
+        Signal::Latency => Some(linear(latency, 10, 200)),
+        Signal::Jitter => measurement.jitter().map(|jitter| linear(jitter, 0, 50)),
+        Signal::Loss => Some(1.0 - measurement.loss()),
+        Signal::Cold => measurement.cold.map(|cold| linear(cold, 50, 500)),
+        Signal::Correctness => checks.correct.map(|correct| f64::from(u8::from(correct))),
+        Signal::Large => checks.delivery.map(|delivery| match delivery {
+            Delivery::Udp(_) => 1.0,
+            Delivery::Tcp(_) => 0.5,
+            Delivery::Truncated(_) => 0.0,
+        }),
+    };
+    let parts: Vec<(Signal, f64, f64)> = Signal::ALL
+        .into_iter()
+        .filter(|signal| weights.get(*signal) > 0.0)
+        .filter_map(|signal| Some((signal, measured(signal)?, weights.get(signal))))
+        .collect();
+    let total: f64 = parts.iter().map(|(_, _, weight)| weight).sum();
+    let combined = if total > 0.0 {
+        parts
+            .iter()
+            .map(|(_, score, weight)| score * weight)
+            .sum::<f64>()
+            / total
+    } else {
+        0.0
+    };
+    Health {
+        score: percent(combined),
+        parts: parts
+            .into_iter()
+            .map(|(signal, score, weight)| (signal, percent(score), weight))
+            .collect(),
+    }
+}
+
+/// 1 up to `full` milliseconds, 0 from `none`, linear in between.
+fn linear(value: Duration, full: u64, none: u64) -> f64 {
+    let ms = value.as_secs_f64() * 1000.0;
+    (1.0 - (ms - full as f64) / (none - full) as f64).clamp(0.0, 1.0)
+}
+
+/// A 0-1 score as a whole percentage.
+fn percent(score: f64) -> u8 {
+    (score * 100.0).round().clamp(0.0, 100.0) as u8
+}
@@ -0,0 +1,61 @@
+//! Detection of resolvers that answer nonexistent domains with an address,
+//! typically to send typos to an ad or search page.
+
+use crate::{
+    backend::{self, ProbeSettings},
+    dns::{self, NXDOMAIN},
+};
+use fastrand::Rng;
+use std::{fmt, net::IpAddr};
+
+/// Length of the random label of the probed domain.
+const LABEL_LENGTH: usize = 20;
+
+/// How a resolver answered a domain that does not exist.
+pub enum Nxdomain {
+    /// NXDOMAIN (or an empty answer) came back, as it should
+    Honest,
+    /// The nonexistent domain resolved, to these addresses
+    Hijacked(Vec<IpAddr>),
+    /// The check could not be performed, with the reason
+    Unknown(String),
+}
+
+impl fmt::Display for Nxdomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nxdomain::Honest => write!(f, "answers NXDOMAIN"),
+            Nxdomain::Hijacked(addresses) => {
+                let addresses: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+                write!(f, "rewrites NXDOMAIN to {}", addresses.join(", "))
+            }
+            Nxdomain::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// A random, certainly unregistered `.com` name no resolver has cached.
+pub fn random_name(rng: &mut Rng) -> String {
+    let label: String = std::iter::repeat_with(|| rng.lowercase())
+        .take(LABEL_LENGTH)
+        .collect();
+    format!("wisp-{}.com", label)
+}
+
+/// Looks up a random, certainly unregistered `.com` name on the resolver at
+/// `address`, the way `settings` probe it (see [`backend::lookup`]).
+///
+/// The name is fresh for every check so no cache can answer for the
+/// resolver; `.com` is used because hijackers commonly leave reserved names
+/// like `.invalid` alone.
+pub fn check(address: &str, settings: &ProbeSettings, rng: &mut Rng) -> Nxdomain {
+    match backend::lookup(address, &random_name(rng), dns::TYPE_A, settings) {
+        Ok(response) if response.rcode == NXDOMAIN => Nxdomain::Honest,
+        Ok(response) if response.rcode == 0 && !response.addresses.is_empty() => {
+            Nxdomain::Hijacked(response.addresses)
+        }
+        Ok(response) if response.rcode == 0 => Nxdomain::Honest,
+        Ok(response) => Nxdomain::Unknown(format!("response code {}", response.rcode)),
+        Err(err) => Nxdomain::Unknown(err.to_string()),
+    }
+}
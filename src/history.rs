@@ -0,0 +1,132 @@
+//! The history log: one JSON line per benchmark run, optionally labelled
+//! with `--tag`, so runs can be compared later with `wisp compare`.
+
+use crate::{error::Error, measurement::Measurement, paths, providers::Provider};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the log in the data directory, see [`paths::data_file`].
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// Lock file next to the log that runs hold while writing it. It is a
+/// separate file because rotation replaces the log itself.
+const LOCK_FILE: &str = "history.jsonl.lock";
+
+/// Number of runs kept, the oldest ones are dropped beyond that.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+/// One benchmark run as stored in the log.
+#[derive(Serialize, Deserialize)]
+pub struct Run {
+    /// When the run finished, RFC 3339 local time
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub results: Vec<Entry>,
+}
+
+/// One server of a [`Run`].
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub ip: String,
+    /// Mean latency, `None` if the server never answered
+    pub latency_ms: Option<f64>,
+    /// Fraction of probes that were lost, between 0.0 and 1.0
+    pub loss: f64,
+}
+
+/// Where the log lives, e.g.
+/// `~/Library/Application Support/wisp/history.jsonl`.
+pub fn path() -> io::Result<PathBuf> {
+    paths::data_file(HISTORY_FILE)
+}
+
+/// Appends the results of a run to the log, then drops the oldest runs
+/// beyond `limit`.
+///
+/// Overlapping runs (a `--watch` and a manual one, say) take turns through
+/// an exclusive lock, and the log is trimmed by writing the kept runs to a
+/// temporary file renamed over it, so neither a concurrent append nor a
+/// crash mid-rotation can leave a torn log behind.
+pub fn record(
+    results: &[(&Provider, Measurement)],
+    tag: Option<&str>,
+    limit: usize,
+) -> Result<(), Error> {
+    let path = path().map_err(|err| Error::Write {
+        path: HISTORY_FILE.to_string(),
+        message: err.to_string(),
+    })?;
+    let write_error = |path: &Path, err: io::Error| Error::Write {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    };
+    let lock_path = path.with_file_name(LOCK_FILE);
+    let lock = File::create(&lock_path).map_err(|err| write_error(&lock_path, err))?;
+    // Released when `lock` is dropped at the end of the function
+    lock.lock().map_err(|err| write_error(&lock_path, err))?;
+    let run = Run {
+        timestamp: Local::now().to_rfc3339(),
+        tag: tag.map(str::to_string),
+        results: results
+            .iter()
+            .map(|(provider, measurement)| Entry {
+                name: provider.name.clone(),
+                ip: provider.ip.clone(),
+                latency_ms: measurement
+                    .latency()
+                    .map(|latency| latency.as_secs_f64() * 1000.0),
+                loss: measurement.loss(),
+            })
+            .collect(),
+    };
+    let line = serde_json::to_string(&run).expect("history entries serialize");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(format!("{}\n", line).as_bytes()))
+        .map_err(|err| write_error(&path, err))?;
+    rotate(&path, limit).map_err(|err| write_error(&path, err))
+}
+
+/// Keeps only the newest `limit` lines of the log, replacing it atomically.
+fn rotate(path: &Path, limit: usize) -> io::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    if lines.len() <= limit {
+        return Ok(());
+    }
+    let kept: String = lines[lines.len() - limit..]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let temporary = path.with_extension("jsonl.tmp");
+    fs::write(&temporary, kept)?;
+    fs::rename(&temporary, path)
+}
+
+/// Reads every run in the log, oldest first, skipping lines that do not
+/// parse. A missing log has no runs.
+pub fn load() -> Vec<Run> {
+    path()
+        .and_then(fs::read_to_string)
+        .ok()
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The most recent run labelled `tag`.
+pub fn latest_tagged<'a>(runs: &'a [Run], tag: &str) -> Option<&'a Run> {
+    runs.iter()
+        .rev()
+        .find(|run| run.tag.as_deref() == Some(tag))
+}
@@ -0,0 +1,71 @@
+//! Addresses of providers given by hostname, cached across runs so the
+//! benchmark times the resolver rather than the lookup of its own name.
+
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, ToSocketAddrs},
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How long a resolved address is reused before the name is looked up again.
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Name of the cache in the cache directory, see [`paths::cache_file`].
+const CACHE_FILE: &str = "hostnames.json";
+
+/// A cached address and when it stops being used, in seconds since the epoch.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Entry {
+    ip: IpAddr,
+    expires: u64,
+}
+
+/// The cache, read from disk on first use.
+fn cache() -> &'static Mutex<HashMap<String, Entry>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let entries = paths::cache_file(CACHE_FILE)
+            .and_then(fs::read_to_string)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Mutex::new(entries)
+    })
+}
+
+/// Seconds since the epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Resolves `host` (with `port` for the system resolver) to an address,
+/// reusing one resolved less than [`CACHE_TTL`] ago, by this or an earlier
+/// run.
+pub fn resolve(host: &str, port: u16) -> io::Result<IpAddr> {
+    let mut cache = cache().lock().unwrap();
+    if let Some(entry) = cache.get(host).filter(|entry| entry.expires > now()) {
+        return Ok(entry.ip);
+    }
+    let ip = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .map(|socket| socket.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address did not resolve"))?;
+    let expires = now() + CACHE_TTL.as_secs();
+    cache.retain(|_, entry| entry.expires > now());
+    cache.insert(host.to_string(), Entry { ip, expires });
+    // Best effort, the next run just resolves again
+    if let Ok(path) = paths::cache_file(CACHE_FILE) {
+        if let Ok(text) = serde_json::to_string_pretty(&*cache) {
+            let _ = fs::write(path, text);
+        }
+    }
+    Ok(ip)
+}
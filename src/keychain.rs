@@ -0,0 +1,52 @@
+//! Secrets kept in the macOS login Keychain through the `security` tool, so
+//! they never sit in plaintext next to wisp's other settings.
+
+use crate::{error::Error, system};
+
+/// Service name every wisp item is filed under in the Keychain.
+const SERVICE: &str = "wisp";
+
+/// Reads the secret stored for `account`, `None` when there is none or no
+/// Keychain to ask (outside macOS, say).
+pub fn get(account: &str) -> Option<String> {
+    let output = system::run(
+        "security",
+        &["find-generic-password", "-s", SERVICE, "-a", account, "-w"],
+    )
+    .ok()?;
+    let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Some(secret).filter(|secret| !secret.is_empty())
+}
+
+/// Stores `secret` for `account`, replacing what was stored before.
+///
+/// The command goes to `security -i`, which reads commands from stdin, so
+/// the secret never appears in the arguments, where `ps` and an error
+/// message would show it. Giving `-w` without a value would keep it out
+/// too, but then `security` prompts for it through the terminal, which a
+/// scheduled run does not have. Interactive mode reports a failed command
+/// on stderr only, its exit status stays zero.
+pub fn set(account: &str, secret: &str) -> Result<(), Error> {
+    let command = format!(
+        "add-generic-password -U -s {} -a {} -w {}\n",
+        quote(SERVICE),
+        quote(account),
+        quote(secret)
+    );
+    let output = system::run_with_input("security", &["-i"], Some(&command))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if stderr.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Command {
+            command: format!("security -i (add-generic-password for {})", account),
+            message: stderr,
+        })
+    }
+}
+
+/// Quotes a word of a `security -i` command line, where whitespace
+/// separates words and a backslash escapes the next character.
+fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
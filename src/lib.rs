@@ -0,0 +1,41 @@
+//! Measuring DNS providers and choosing the fastest one; the `wisp` binary
+//! drives these modules to reconfigure macOS.
+
+pub mod backend;
+pub mod cache;
+pub mod cli;
+pub mod consistency;
+pub mod correctness;
+pub mod dns;
+pub mod doctor;
+pub mod error;
+pub mod filtering;
+pub mod health;
+pub mod hijack;
+pub mod history;
+pub mod hostnames;
+pub mod keychain;
+pub mod lock;
+pub mod logging;
+pub mod measurement;
+pub mod minimization;
+pub mod mock;
+pub mod paths;
+pub mod ping;
+pub mod pop;
+pub mod profile;
+pub mod providers;
+pub mod proxy;
+pub mod remote;
+pub mod report;
+pub mod schedule;
+pub mod score;
+pub mod scutil;
+pub mod selection;
+pub mod smoothing;
+pub mod source;
+pub mod stats;
+pub mod system;
+pub mod tls;
+pub mod trace;
+pub mod truncation;
@@ -0,0 +1,36 @@
+//! The run lock behind `--once`, which keeps a scheduled run from
+//! overlapping one that is still going, so two instances never reconfigure
+//! DNS at the same time.
+
+use crate::{error::Error, logging::log, paths};
+use std::fs::{File, TryLockError};
+
+/// Name of the lock file in the data directory, see [`paths::data_file`].
+const LOCK_FILE: &str = "wisp.lock";
+
+/// The run lock, held until dropped or the process exits.
+pub struct RunLock {
+    _file: File,
+}
+
+/// Takes the run lock. With `wait`, blocks until the run holding it
+/// finishes; otherwise fails right away with [`Error::AlreadyRunning`].
+pub fn acquire(wait: bool) -> Result<RunLock, Error> {
+    let write_error = |path: String, err: std::io::Error| Error::Write {
+        path,
+        message: err.to_string(),
+    };
+    let path =
+        paths::data_file(LOCK_FILE).map_err(|err| write_error(LOCK_FILE.to_string(), err))?;
+    let file = File::create(&path).map_err(|err| write_error(path.display().to_string(), err))?;
+    match file.try_lock() {
+        Ok(()) => return Ok(RunLock { _file: file }),
+        Err(TryLockError::WouldBlock) if wait => {}
+        Err(TryLockError::WouldBlock) => return Err(Error::AlreadyRunning),
+        Err(TryLockError::Error(err)) => return Err(write_error(path.display().to_string(), err)),
+    }
+    log("Another wisp run is in progress, waiting for it to finish");
+    file.lock()
+        .map_err(|err| write_error(path.display().to_string(), err))?;
+    Ok(RunLock { _file: file })
+}
@@ -0,0 +1,93 @@
+//! Operational logging to stderr, rendered as text or JSON lines.
+
+use crate::cli::LogFormat;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Format every log event is rendered in, set once at startup.
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// A log event as rendered by the JSON format.
+#[derive(Serialize)]
+struct Event<'a> {
+    ts: String,
+    level: &'static str,
+    message: &'a str,
+}
+
+/// Severity of a log event.
+#[derive(Clone, Copy)]
+enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// Selects how log events are rendered. Only the first call has an effect.
+pub fn init(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Logs a message with a timestamp prefix.
+///
+/// Logs go to stderr so results printed on stdout can be piped.
+///
+/// # Arguments
+/// * `message` - The message to be logged
+pub fn log(message: &str) {
+    emit(Level::Info, message);
+}
+
+/// Logs a warning about something that was skipped or degraded.
+pub fn warn(message: &str) {
+    emit(Level::Warn, message);
+}
+
+/// Logs an error that stops the current operation.
+pub fn error(message: &str) {
+    emit(Level::Error, message);
+}
+
+/// Writes one event to stderr in the configured format.
+///
+/// JSON events carry `ts`, `level` and `message` fields; the blank-line
+/// spacing used by the text format is trimmed from the message.
+fn emit(level: Level, message: &str) {
+    let now = chrono::Local::now();
+    match FORMAT.get().copied().unwrap_or(LogFormat::Human) {
+        LogFormat::Human => {
+            let prefix = match level {
+                Level::Info => "",
+                Level::Warn => "Warning: ",
+                Level::Error => "Error: ",
+            };
+            eprintln!(
+                "[{}] {}{}",
+                now.format("%Y-%m-%d %H:%M:%S"),
+                prefix,
+                message
+            );
+        }
+        LogFormat::Json => {
+            let event = Event {
+                ts: now.to_rfc3339(),
+                level: level.as_str(),
+                message: message.trim(),
+            };
+            eprintln!(
+                "{}",
+                serde_json::to_string(&event).expect("Failed to serialize log event")
+            );
+        }
+    }
+}
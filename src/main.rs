@@ -7,111 +7,1885 @@
 //! - Latency testing for multiple DNS providers
 //! - Automatic configuration of the fastest DNS server
 
-use regex::Regex;
+use backend::{MeasurementBackend, ProbeSettings};
+use cache::CacheStats;
+use clap::Parser;
+use cli::{ApplyWith, Cli, Column, Command as CliCommand, Method, Once, Strategy, Unattended};
+use error::Error;
+use fastrand::Rng;
+use logging::{error, log, warn};
+use measurement::Measurement;
+use providers::{
+    builtin_providers, dedup_providers, first_per_family, is_valid_address,
+    is_valid_nextdns_profile, local_resolver, parse_provider_document, parse_provider_list,
+    Provider,
+};
+use report::{format_duration, print_results, write_html, NumberFormat};
+use selection::rank_by_latency;
+use smoothing::{Ewma, SMOOTHED_PING_COUNT};
 use std::{
-    process::Command,
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal, Read},
+    net::IpAddr,
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
     thread,
     time::{Duration, Instant},
 };
+use system::{
+    get_current_dns, get_manual_dns, get_persistent_dns, get_proxies, get_scope_resolvers,
+    has_default_route, remove_split_dns, restore_dns, set_dns, set_dns_automatic, set_split_dns,
+    Changes, RollbackGuard, Route, SERVICE,
+};
+use wisp::{
+    backend, cache, cli, consistency, correctness, doctor, error, filtering, health, history,
+    keychain, lock, logging, measurement, minimization, pop, profile, providers, remote, report,
+    selection, smoothing, source, stats, system, trace, truncation,
+};
 
-/// Logs a message with a timestamp prefix.
-///
-/// # Arguments
-/// * `message` - The message to be logged
-fn log(message: &str) {
-    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-    println!("[{}] {}", timestamp, message);
-}
-
-/// Retrieves the current DNS server configuration from the system.
-///
-/// Uses the `scutil` command to query DNS settings and parses the output
-/// to extract nameserver IP addresses.
-fn get_current_dns() -> Vec<String> {
-    let output = Command::new("scutil")
-        .arg("--dns")
-        .output()
-        .expect("Failed to execute scutil command");
-
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    let first_section = output_str
-        .split("DNS configuration (for scoped queries)")
-        .next()
-        .unwrap();
-
-    let ip_pattern = Regex::new(r"nameserver\[\d\]\s*:\s*([^\s]+)").unwrap();
-
-    first_section
-        .lines()
-        .filter(|line| line.trim().starts_with("nameserver"))
-        .filter_map(|line| {
-            ip_pattern
-                .captures(line)
-                .and_then(|cap| cap.get(1))
-                .map(|ip| ip.as_str().to_string())
-        })
-        .collect()
-}
+/// Least number of pings per server for a head-to-head `--compare`.
+const COMPARE_PING_COUNT: usize = 20;
+
+/// Number of pings per server when checking only the current DNS.
+const CURRENT_PING_COUNT: usize = 10;
 
-/// Sets the DNS servers for the Wi-Fi interface.
+/// Keychain account the `--nextdns-profile` ID is saved under.
+const NEXTDNS_ACCOUNT: &str = "nextdns-profile";
+
+/// How much slower than the fastest alternative automatic DNS may be and
+/// still be called good enough by `--compare-to-automatic`.
+const AUTOMATIC_MARGIN: Duration = Duration::from_millis(5);
+
+/// How often a measurement is started over because the network changed
+/// under it before the run gives up, see [`on_stable_route`].
+const ROUTE_RESTARTS: usize = 1;
+
+/// Minimum gap between starting the measurements of two servers.
+const PROBE_SPACING: Duration = Duration::from_millis(20);
+
+/// Set when `--watch` is asked to stop (SIGINT, SIGTERM or SIGHUP), so the
+/// loop can end between steps instead of being killed halfway through one.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// How often a waiting `--watch` checks whether it was asked to stop.
+const SHUTDOWN_POLL: Duration = Duration::from_secs(1);
+
+/// Logs the effective settings of a run in verbose mode, so logs of
+/// different runs can be compared.
 ///
-/// * Includes a 2-second delay after setting DNS to allow changes to take effect
-/// * Only affects the Wi-Fi interface
-fn set_dns(dns: &str) {
-    log(&format!("Setting DNS servers to: {}", dns));
-    Command::new("networksetup")
-        .args(["-setdnsservers", "Wi-Fi", dns])
-        .output()
-        .expect(&format!("Failed to set DNS to {}", dns));
+/// `interface` is the network service whose DNS will be changed, `None` for
+/// modes that only measure.
+fn log_configuration(
+    cli: &Cli,
+    settings: &ProbeSettings,
+    providers: &[Provider],
+    interface: Option<&str>,
+) {
+    let seed = cli.seed.expect("seed is set at startup");
+    trace::settings(settings, seed);
+    if !cli.verbose {
+        if cli.shuffle || settings.interleave.is_some() {
+            log(&format!(
+                "Random order seed {} (--seed {} repeats it)",
+                seed, seed
+            ));
+        }
+        return;
+    }
+    let source = match (&cli.providers, &cli.providers_url) {
+        (Some(path), _) if path.as_os_str() == "-" => "stdin".to_string(),
+        (Some(path), _) => path.display().to_string(),
+        (None, Some(url)) => url.clone(),
+        (None, None) => "built-in list".to_string(),
+    };
+    let mut filters = vec!["duplicate addresses removed"];
+    if cli.quick {
+        filters.push("primary server per family (--quick)");
+    }
+    if cli.max_providers.is_some() {
+        filters.push("capped by --max-providers");
+    }
+    let applied = match (cli.servers, cli.no_diverse) {
+        (1, _) => "fastest server".to_string(),
+        (count, false) => format!("{} fastest servers from different providers", count),
+        (count, true) => format!("{} fastest servers", count),
+    };
+    let sort = if interface.is_some() {
+        selection::from_cli(cli, Vec::new()).describe()
+    } else {
+        "mean latency".to_string()
+    };
 
-    // Give some time for DNS changes to take effect
-    thread::sleep(Duration::from_secs(2));
-    log("DNS settings applied");
+    log("\nConfiguration:");
+    log(&format!(
+        "  interface: {}",
+        interface.unwrap_or("none (measurement only)")
+    ));
+    log(&format!("  servers:   {} from {}", providers.len(), source));
+    log(&format!("  filters:   {}", filters.join(", ")));
+    log(if cli.shuffle {
+        "  order:     shuffled"
+    } else {
+        "  order:     as listed"
+    });
+    log(&format!(
+        "  seed:      {} (--seed {} repeats the run)",
+        seed, seed
+    ));
+    let mut protocols: Vec<String> = settings
+        .methods
+        .iter()
+        .map(|method| backend::backend(*method).name().to_string())
+        .collect();
+    if settings.fallback {
+        protocols = vec![format!("{} (first to answer)", protocols.join(", then "))];
+    } else {
+        protocols[0].push_str(" (ranking)");
+    }
+    if cli.include_local && settings.methods.contains(&Method::Icmp) {
+        protocols.push("udp instead of icmp for the local resolver".to_string());
+    }
+    log(&format!("  protocol:  {}", protocols.join(", ")));
+    log(&format!(
+        "  samples:   {} per server after {} warmup",
+        settings.count, settings.warmup
+    ));
+    if !settings.record_types.is_empty() {
+        let types: Vec<&str> = settings
+            .record_types
+            .iter()
+            .map(|record_type| record_type.name())
+            .collect();
+        log(&format!("  records:   {} (combined)", types.join(", ")));
+    }
+    log(&format!("  timeout:   {:?} per probe", settings.timeout));
+    log(&format!(
+        "  interval:  {:?} or more between probes to a server",
+        settings.probe_interval
+    ));
+    if let Some(vrf) = &cli.vrf {
+        log(&format!("  vrf:       {}", vrf));
+    }
+    if let Some(source) = cli.source {
+        log(&format!("  source:    {}", source));
+    }
+    if let Some(proxy) = &settings.proxy {
+        log(&format!("  proxy:     {} for dot and doh", proxy));
+    }
+    log(&format!(
+        "  parallel:  up to {} servers, started {:?} apart",
+        settings.max_concurrent, PROBE_SPACING
+    ));
+    if interface.is_some() {
+        log(&format!(
+            "  settle:    {:?} after each change",
+            settle_delay(cli)
+        ));
+    }
+    log(&format!("  sort:      {}", sort));
+    if interface.is_some() {
+        log(&format!("  applying:  {}", applied));
+    }
 }
 
-/// Sets DNS configuration to automatic (DHCP) mode.
+/// Measures the latency to a DNS server with every selected `--method`.
 ///
-/// This removes any manually configured DNS servers and allows
-/// the system to obtain DNS settings automatically from DHCP.
-fn set_dns_automatic() {
-    log("Setting DNS to automatic (empty)");
-    Command::new("networksetup")
-        .args(["-setdnsservers", "Wi-Fi", "empty"])
-        .output()
-        .expect("Failed to set DNS to automatic");
+/// The first method's measurement is returned for ranking, the others are
+/// only logged for comparison; with `--transport`, the methods are tried in
+/// order instead and the first one that gets answers is returned. Loopback
+/// resolvers are never pinged: ICMP to localhost says nothing about the
+/// resolver, so they are timed with UDP queries instead. With
+/// `--mock-latencies` nothing is sent, the file's numbers are returned.
+fn measure_latency(dns: &str, settings: &ProbeSettings) -> Result<Measurement, Error> {
+    log(&format!("Testing latency for {}", dns));
+    if let Some(mock) = &settings.mock {
+        let measurement = mock.measurement(dns);
+        log_latency(dns, &measurement);
+        return Ok(measurement);
+    }
+    if providers::is_system(dns) {
+        let measurement = backend::SystemResolver.measure(dns, settings)?;
+        log_latency(dns, &measurement);
+        return Ok(measurement);
+    }
+    let loopback = dns.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    let mut ranked = None;
+    for (index, method) in settings.methods.iter().enumerate() {
+        let method = match method {
+            Method::Icmp if loopback => Method::Udp,
+            method => *method,
+        };
+        let backend = backend::backend(method);
+        let label = if settings.methods.len() > 1 {
+            format!("{} over {}", dns, backend.name())
+        } else {
+            dns.to_string()
+        };
+        let measurement = measure_record_types(method, dns, settings, &label)?;
+        if settings.fallback {
+            if measurement.reachable() || index + 1 == settings.methods.len() {
+                log_latency(&label, &measurement);
+                return Ok(measurement);
+            }
+            log(&format!("No answer from {}, falling back", label));
+            continue;
+        }
+        log_latency(&label, &measurement);
+        ranked.get_or_insert(measurement);
+    }
+    Ok(ranked.unwrap_or_default())
+}
 
-    thread::sleep(Duration::from_secs(2));
-    log("DNS set to automatic mode");
+/// Measures `dns` with `method` once per `--record-types` type, logging each
+/// type's figures under `label`, and returns all their probes combined. Only
+/// the first type gets the warmup probes. Without `--record-types`, and over
+/// ICMP which asks for no records, this is a single measurement.
+fn measure_record_types(
+    method: Method,
+    dns: &str,
+    settings: &ProbeSettings,
+    label: &str,
+) -> Result<Measurement, Error> {
+    let backend = backend::backend(method);
+    if settings.record_types.is_empty() || method == Method::Icmp {
+        return backend.measure(dns, settings);
+    }
+    let mut combined = Measurement::default();
+    for (index, record_type) in settings.record_types.iter().enumerate() {
+        let typed = ProbeSettings {
+            record_type: record_type.code(),
+            warmup: if index == 0 { settings.warmup } else { 0 },
+            ..settings.clone()
+        };
+        let measurement = backend.measure(dns, &typed)?;
+        log_latency(&format!("{} ({})", label, record_type.name()), &measurement);
+        combined.merge(measurement);
+    }
+    Ok(combined)
 }
 
-/// Measures the latency to a DNS server using ping.
-fn measure_latency(dns: &str) -> Duration {
-    log(&format!("Testing latency for {}", dns));
-    let start = Instant::now();
-    Command::new("ping")
-        .args(["-c", "3", dns])
-        .output()
-        .expect("Failed to ping DNS");
-    let latency = start.elapsed() / 3;
-    log(&format!("Latency for {}: {:?}", dns, latency));
-    latency
+/// Logs the latency and loss of one measurement of `label`.
+fn log_latency(label: &str, measurement: &Measurement) {
+    match measurement.latency() {
+        Some(latency) => log(&format!(
+            "Latency for {}: {:?} ({:.0}% loss{}{})",
+            label,
+            latency,
+            measurement.loss() * 100.0,
+            measurement
+                .cold
+                .map(|cold| format!(", {:?} cold", cold))
+                .unwrap_or_default(),
+            measurement
+                .setup
+                .map(|setup| format!(", {:?} to connect", setup))
+                .unwrap_or_default()
+        )),
+        None => warn(&format!("no response from {}", label)),
+    }
 }
 
 /// Prints the current DNS configuration.
 ///
 /// Retrieves and displays the current DNS servers configured on the system.
 /// If no DNS servers are configured (empty list), indicates that DNS is set
-/// to automatic (DHCP) mode.
-fn print_current_dns() {
+/// to automatic (DHCP) mode. Returns the servers that were printed.
+fn print_current_dns() -> Vec<String> {
     let current_dns = get_current_dns();
     log("Current DNS servers:");
     if current_dns.is_empty() {
         log("  • Automatic (DHCP)");
     } else {
-        for dns in current_dns {
+        for dns in &current_dns {
             log(&format!("  • {}", dns));
         }
     }
+    current_dns
+}
+
+/// Warns when a system proxy or PAC file is configured.
+///
+/// Browsers behind a proxy let the proxy resolve names, so the measured
+/// resolver latency may not reflect what browsing actually experiences.
+fn warn_about_proxies() {
+    let proxies = get_proxies();
+    if proxies.is_empty() {
+        return;
+    }
+    warn(&format!(
+        "system proxy configuration detected ({}); measured DNS latency may not reflect how browsers resolve names on this machine",
+        proxies.join(", ")
+    ));
+}
+
+/// Builds a one-line summary of a DNS switch to `fastest`, e.g.
+/// "Switched to Cloudflare Primary (11ms, was 40ms)", returned with the
+/// previous primary's latency.
+///
+/// The previous primary is measured separately if it was not one of the
+/// tested providers. Returns `None` when the fastest server is already the
+/// primary resolver.
+fn change_summary(
+    original_dns: &[String],
+    fastest: (&Provider, Duration),
+    results: &[(&Provider, Measurement)],
+    settings: &ProbeSettings,
+) -> Option<(String, Option<Duration>)> {
+    let (fastest, fastest_latency) = fastest;
+    let previous = original_dns.first();
+    if previous == Some(&fastest.ip) {
+        return None;
+    }
+
+    let previous_latency = previous.and_then(|ip| {
+        results
+            .iter()
+            .find(|(provider, _)| &provider.ip == ip)
+            .map(|(_, measurement)| measurement.latency())
+            .unwrap_or_else(|| {
+                measure_latency(ip, settings)
+                    .ok()
+                    .and_then(|measurement| measurement.latency())
+            })
+    });
+    let summary = match previous_latency {
+        Some(latency) => format!(
+            "Switched to {} ({}ms, was {}ms)",
+            fastest.name,
+            fastest_latency.as_millis(),
+            latency.as_millis()
+        ),
+        None => format!(
+            "Switched to {} ({}ms)",
+            fastest.name,
+            fastest_latency.as_millis()
+        ),
+    };
+    Some((summary, previous_latency))
+}
+
+/// Estimates the lookup time a switch from a server answering in
+/// `previous` to one answering in `latency` saves at `queries_per_day`,
+/// e.g. "~3000 queries/day × 30ms saved ≈ 1.5min/day, 45min/month".
+/// Returns `None` when the switch saves nothing.
+fn savings_estimate(previous: Duration, latency: Duration, queries_per_day: u64) -> Option<String> {
+    let saved = previous
+        .checked_sub(latency)
+        .filter(|saved| !saved.is_zero())?;
+    let per_day = saved.as_secs_f64() * queries_per_day as f64;
+    let span = |secs: f64| {
+        if secs < 120.0 {
+            format!("{:.0}s", secs)
+        } else if secs < 7200.0 {
+            format!("{:.0}min", secs / 60.0)
+        } else {
+            format!("{:.1}h", secs / 3600.0)
+        }
+    };
+    Some(format!(
+        "~{} queries/day × {}ms saved ≈ {}/day, {}/month",
+        queries_per_day,
+        saved.as_millis(),
+        span(per_day),
+        span(per_day * 30.0)
+    ))
+}
+
+/// Logs why `winner` was chosen: its own numbers, its margin over the
+/// runner-up and over the current DNS, and whether `--sticky` or `--region`
+/// decided it. `pops` are the POPs found for `--region`, by address.
+fn explain_choice(
+    results: &[(&Provider, Measurement)],
+    winner: &Provider,
+    original_dns: &[String],
+    pops: &HashMap<String, String>,
+    cli: &Cli,
+) {
+    let measured = |provider: &Provider| {
+        results
+            .iter()
+            .find(|(other, _)| other.ip == provider.ip)
+            .map(|(_, measurement)| measurement)
+    };
+    let Some(won) = measured(winner) else {
+        return;
+    };
+    let Some(latency) = won.latency() else {
+        return;
+    };
+    let candidates: Vec<(&Provider, Duration)> = results
+        .iter()
+        .filter(|(provider, _)| !provider.baseline && provider.ip != winner.ip)
+        .filter_map(|(provider, measurement)| Some((*provider, measurement.latency()?)))
+        .collect();
+
+    let show = |duration| format_duration(duration, NumberFormat::from_cli(cli));
+    log(&format!("\nWhy {}:", winner.name));
+    log(&format!(
+        "  {} mean over {} of {} replies, {:.0}% loss{}",
+        show(latency),
+        won.samples.len(),
+        won.sent,
+        won.loss() * 100.0,
+        won.jitter()
+            .map(|jitter| format!(", {} jitter", show(jitter)))
+            .unwrap_or_default()
+    ));
+
+    let current = original_dns.first();
+    let is_current = current == Some(&winner.ip);
+    let in_region = |provider: &Provider| {
+        cli.region
+            .as_ref()
+            .zip(pops.get(&provider.ip))
+            .is_some_and(|(region, found)| pop::in_region(found, region))
+    };
+    if let Some(region) = &cli.region {
+        match pops.get(&winner.ip) {
+            Some(found) => log(&format!(
+                "  answered from POP {}, {} --region {}",
+                found,
+                if in_region(winner) { "in" } else { "outside" },
+                region
+            )),
+            None => log("  did not report which POP answered"),
+        }
+    }
+    match candidates.first() {
+        Some((runner_up, runner_up_latency)) if *runner_up_latency >= latency => log(&format!(
+            "  {} ahead of the runner-up {} ({})",
+            show(*runner_up_latency - latency),
+            runner_up.name,
+            show(*runner_up_latency)
+        )),
+        Some((runner_up, runner_up_latency))
+            if selection::strategy(cli) == Strategy::Sticky && is_current =>
+        {
+            log(&format!(
+                "  {} was {} faster, but the current DNS gets a {}ms --sticky bonus and the margin was smaller",
+                runner_up.name,
+                show(latency - *runner_up_latency),
+                cli.sticky_bonus
+            ))
+        }
+        Some((runner_up, runner_up_latency)) if in_region(winner) && !in_region(runner_up) => {
+            log(&format!(
+                "  {} was {} faster but outside --region, and the margin was under {}",
+                runner_up.name,
+                show(latency - *runner_up_latency),
+                show(pop::REGION_MARGIN)
+            ))
+        }
+        Some((runner_up, runner_up_latency)) => log(&format!(
+            "  ranked ahead of {} ({})",
+            runner_up.name,
+            show(*runner_up_latency)
+        )),
+        None => log("  the only server that answered"),
+    }
+
+    match current {
+        _ if is_current => log("  already your current DNS, so nothing changes"),
+        Some(ip) => match results.iter().find(|(provider, _)| &provider.ip == ip) {
+            Some((provider, measurement)) => match measurement.latency() {
+                Some(current_latency) if current_latency >= latency => log(&format!(
+                    "  {} faster than your current {} ({})",
+                    show(current_latency - latency),
+                    provider.name,
+                    show(current_latency)
+                )),
+                Some(current_latency) => log(&format!(
+                    "  your current {} measured {}",
+                    provider.name,
+                    show(current_latency)
+                )),
+                None => log(&format!("  your current {} did not answer", provider.name)),
+            },
+            None => log(&format!(
+                "  your current DNS ({}) was not in the test set",
+                ip
+            )),
+        },
+        None => log("  no DNS server was configured before"),
+    }
+}
+
+/// Shows a macOS notification through `osascript`.
+///
+/// A failed notification is only logged, the DNS change already succeeded.
+fn notify(message: &str) {
+    let script = format!(
+        "display notification \"{}\" with title \"wisp\"",
+        message.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    if let Err(err) = system::run("osascript", &["-e", &script]) {
+        warn(&format!("failed to show notification: {}", err));
+    }
+}
+
+/// Loads the providers to test: the `--providers` list if given (a file, or
+/// stdin for "-"), the `--providers-url` list, otherwise the built-in
+/// providers. With `--include-local` the loopback resolver is added as a
+/// baseline.
+fn load_providers(cli: &Cli) -> Vec<Provider> {
+    let mut providers = match (&cli.providers, &cli.providers_url) {
+        (Some(path), _) => read_provider_list(path),
+        (None, Some(url)) => remote::fetch_provider_list(url).unwrap_or_else(|err| {
+            error(&err.to_string());
+            process::exit(1);
+        }),
+        (None, None) => with_nextdns_profile(builtin_providers(), cli),
+    };
+    if cli.include_local {
+        providers.insert(0, local_resolver());
+    }
+    providers
+}
+
+/// Swaps the shared NextDNS address for the user's own profile, given with
+/// `--nextdns-profile` (which saves it to the Keychain) or saved by an
+/// earlier run.
+fn with_nextdns_profile(providers: Vec<Provider>, cli: &Cli) -> Vec<Provider> {
+    let profile = match &cli.nextdns_profile {
+        Some(profile) => {
+            match keychain::set(NEXTDNS_ACCOUNT, profile) {
+                Ok(()) => log("Saved the NextDNS profile ID to the Keychain"),
+                Err(err) => warn(&format!("could not save the NextDNS profile ID: {}", err)),
+            }
+            Some(profile.clone())
+        }
+        None => keychain::get(NEXTDNS_ACCOUNT).filter(|profile| is_valid_nextdns_profile(profile)),
+    };
+    let Some(profile) = profile else {
+        return providers;
+    };
+    let mut replaced = false;
+    providers
+        .into_iter()
+        .filter_map(|provider| {
+            if provider.family != "NextDNS" {
+                Some(provider)
+            } else if replaced {
+                None
+            } else {
+                replaced = true;
+                Some(providers::nextdns(&profile))
+            }
+        })
+        .collect()
+}
+
+/// Reads a `--providers` list, exiting if it cannot be read.
+///
+/// Files ending in `.json` or `.toml` use the same structured format as
+/// `--providers-url`, anything else the line-based format.
+fn read_provider_list(path: &Path) -> Vec<Provider> {
+    let text = if path.as_os_str() == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map(|_| text)
+    } else {
+        fs::read_to_string(path)
+    };
+    let structured = path
+        .extension()
+        .is_some_and(|extension| extension == "json" || extension == "toml");
+    let providers = match text {
+        Ok(text) if structured => parse_provider_document(&text),
+        Ok(text) => Ok(parse_provider_list(&text)),
+        Err(err) => Err(err.to_string()),
+    };
+    providers.unwrap_or_else(|err| {
+        error(&format!(
+            "failed to read providers from {}: {}",
+            path.display(),
+            err
+        ));
+        process::exit(1);
+    })
+}
+
+/// Drops providers whose address is not a valid IP or hostname.
+///
+/// Every invalid entry is reported before any test starts. If nothing valid
+/// remains, the tool exits with an error instead of running an empty test.
+fn validate_providers(providers: Vec<Provider>) -> Vec<Provider> {
+    let (valid, invalid): (Vec<Provider>, Vec<Provider>) = providers
+        .into_iter()
+        .partition(|provider| is_valid_address(&provider.ip));
+
+    for provider in &invalid {
+        warn(&format!(
+            "skipping {} - invalid address {:?}",
+            provider.name, provider.ip
+        ));
+    }
+    if valid.is_empty() {
+        error("none of the DNS servers has a valid address, nothing to test");
+        process::exit(1);
+    }
+    valid
+}
+
+/// Validates the providers and drops duplicate addresses so no resolver is
+/// probed twice. With `--quick`, only each family's primary is kept, and with
+/// `--shuffle` the test order is randomized. `--max-providers` then caps the
+/// list, which after shuffling makes it a random sample.
+fn prepare_providers(providers: Vec<Provider>, cli: &Cli, rng: &mut Rng) -> Vec<Provider> {
+    let (mut providers, duplicates) = dedup_providers(validate_providers(providers));
+    if cli.verbose {
+        for (duplicate, original) in &duplicates {
+            log(&format!(
+                "Skipping {} ({}), same server as {}",
+                duplicate.name, duplicate.ip, original
+            ));
+        }
+    }
+    if cli.quick {
+        providers = first_per_family(providers);
+        log(&format!(
+            "Quick mode: testing {} primary servers",
+            providers.len()
+        ));
+    }
+    if cli.shuffle {
+        rng.shuffle(&mut providers);
+    }
+    if let Some(max) = cli.max_providers.filter(|max| providers.len() > *max) {
+        log(&format!(
+            "Testing {} of {} servers ({})",
+            max,
+            providers.len(),
+            if cli.shuffle {
+                "random sample"
+            } else {
+                "first in list order"
+            }
+        ));
+        providers.truncate(max);
+    }
+    providers
+}
+
+/// Adds the current servers that are not on the list yet, for
+/// `--keep-if-best` to race them.
+fn add_current_servers(providers: &mut Vec<Provider>, current: &[String]) {
+    for ip in current {
+        if !providers.iter().any(|provider| &provider.ip == ip) {
+            providers.push(Provider::new(ip, ip, "Current DNS server"));
+        }
+    }
+}
+
+/// Looks up the DNS servers DHCP hands out for `--compare-to-automatic`,
+/// empty without the flag.
+fn automatic_servers(cli: &Cli) -> Vec<String> {
+    if !cli.compare_to_automatic {
+        return Vec::new();
+    }
+    let servers = match system::default_interface() {
+        Some(device) => system::dhcp_dns(&device).unwrap_or_else(|err| {
+            warn(&format!(
+                "could not read the DHCP lease of {}: {}",
+                device, err
+            ));
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    if servers.is_empty() {
+        warn("no DNS servers from DHCP found, nothing to compare automatic DNS with");
+    }
+    servers
+}
+
+/// Adds the DHCP servers from [`automatic_servers`] as baselines, except
+/// those already in the list, which then stand in for them.
+fn add_automatic_servers(providers: &mut Vec<Provider>, automatic: &[String]) {
+    for ip in automatic {
+        if !providers.iter().any(|provider| &provider.ip == ip) {
+            providers.push(providers::automatic_resolver(ip));
+        }
+    }
+}
+
+/// Says whether staying on automatic DNS would do: it would when the
+/// fastest DHCP server is within [`AUTOMATIC_MARGIN`] of the fastest server
+/// that can be applied.
+fn report_automatic(
+    automatic: &[String],
+    results: &[(&Provider, Measurement)],
+    numbers: NumberFormat,
+) {
+    if automatic.is_empty() {
+        return;
+    }
+    let fastest = |automatic_side: bool| {
+        results
+            .iter()
+            .filter(|(provider, _)| automatic.contains(&provider.ip) == automatic_side)
+            .filter(|(provider, _)| automatic_side || !provider.baseline)
+            .filter_map(|(provider, measurement)| Some((provider, measurement.latency()?)))
+            .min_by_key(|(_, latency)| *latency)
+    };
+    let Some((dhcp, dhcp_latency)) = fastest(true) else {
+        warn(&format!(
+            "automatic DNS ({}) did not answer",
+            automatic.join(", ")
+        ));
+        return;
+    };
+    let Some((public, public_latency)) = fastest(false) else {
+        return;
+    };
+    let verdict = if dhcp_latency <= public_latency + AUTOMATIC_MARGIN {
+        "staying on automatic would be fine".to_string()
+    } else {
+        format!(
+            "switching saves {}",
+            format_duration(dhcp_latency - public_latency, numbers)
+        )
+    };
+    log(&format!(
+        "\nAutomatic DNS: {} at {}, fastest alternative {} at {}, {}",
+        dhcp.ip,
+        format_duration(dhcp_latency, numbers),
+        public.name,
+        format_duration(public_latency, numbers),
+        verdict
+    ));
+}
+
+/// The width of every column of a plain text table, header row included: its
+/// longest value in characters.
+fn column_widths<const N: usize>(rows: &[[String; N]]) -> [usize; N] {
+    std::array::from_fn(|index| {
+        rows.iter()
+            .map(|row| row[index].chars().count())
+            .max()
+            .unwrap_or(0)
+    })
+}
+
+/// Prints the servers a run would test, in the order it would test them,
+/// after every option that narrows or extends the list.
+fn list_providers(cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    let mut providers = prepare_providers(load_providers(cli), cli, rng);
+    if cli.keep_if_best {
+        add_current_servers(&mut providers, &get_current_dns());
+    }
+    add_automatic_servers(&mut providers, &automatic_servers(cli));
+    if cli.compare_to_system {
+        providers.push(providers::system_resolver());
+    }
+    let mut rows = vec![[
+        "Name".to_string(),
+        "Address".to_string(),
+        "Provider".to_string(),
+    ]];
+    rows.extend(providers.iter().map(|provider| {
+        [
+            provider.name.clone(),
+            provider.ip.clone(),
+            if provider.baseline {
+                format!("{} (baseline, never applied)", provider.family)
+            } else {
+                provider.family.clone()
+            },
+        ]
+    }));
+    let widths = column_widths(&rows);
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    log(&format!("{} server(s) would be tested", providers.len()));
+    Ok(())
+}
+
+/// Measures every provider and returns the results sorted by latency.
+///
+/// At most `settings.max_concurrent` servers are measured at a time, and
+/// starting one waits [`PROBE_SPACING`] after the previous start, so public
+/// resolvers are not hit all at once and rate limits do not skew the
+/// ranking. Servers that never answered are kept, sorted after all reachable
+/// ones.
+///
+/// The very first probe of a run tends to be slow whichever server gets it,
+/// as routes, ARP entries and the local stub resolver warm up, which biased
+/// rankings against the servers listed first. Unless disabled, one discarded
+/// probe goes to the first server that is not a baseline beforehand, the
+/// loopback resolver having no network path to warm; its time is logged so
+/// the size of the effect can be compared with that server's measured
+/// latency. A warm-up that fails is only logged.
+fn benchmark<'a>(
+    providers: &'a [Provider],
+    settings: &ProbeSettings,
+    rng: &mut Rng,
+) -> Result<Vec<(&'a Provider, Measurement)>, Error> {
+    let first = providers.iter().find(|provider| !provider.baseline);
+    if let (true, Some(first)) = (settings.global_warmup, first) {
+        let warmup = ProbeSettings {
+            count: 1,
+            warmup: 0,
+            record_types: Vec::new(),
+            log_pop: false,
+            ..settings.clone()
+        };
+        match measure_latency(&first.ip, &warmup.for_provider(first)) {
+            Ok(measurement) => log(&format!(
+                "Warm-up probe to {}: {} (not counted)",
+                first.ip,
+                measurement
+                    .latency()
+                    .map_or("no answer".to_string(), |latency| format!(
+                        "{:.2?}",
+                        latency
+                    ))
+            )),
+            Err(err) => warn(&format!("warm-up probe to {} failed: {}", first.ip, err)),
+        }
+    }
+    let order: Vec<usize> = (0..providers.len()).collect();
+    let measurements = match settings.interleave {
+        None => measure_in_order(providers, &order, settings)?,
+        Some(passes) => {
+            log(&format!(
+                "Interleaving {} passes of one probe per server",
+                passes
+            ));
+            let mut totals = vec![Measurement::default(); providers.len()];
+            for pass in 0..passes {
+                let mut order = order.clone();
+                rng.shuffle(&mut order);
+                let pass_settings = ProbeSettings {
+                    count: 1,
+                    // Only the first probe of a server warms it up
+                    warmup: if pass == 0 { settings.warmup } else { 0 },
+                    ..settings.clone()
+                };
+                log(&format!("Pass {}/{}", pass + 1, passes));
+                let measured = measure_in_order(providers, &order, &pass_settings)?;
+                for (total, measurement) in totals.iter_mut().zip(measured) {
+                    total.merge(measurement);
+                }
+            }
+            totals
+        }
+    };
+    let mut results: Vec<_> = providers.iter().zip(measurements).collect();
+
+    rank_by_latency(&mut results, |_| Duration::ZERO);
+    trace::measurements(&results);
+    Ok(results)
+}
+
+/// Measures the providers at the positions in `order`, in that order and at
+/// most `settings.max_concurrent` at a time, and returns the measurements in
+/// list order.
+fn measure_in_order(
+    providers: &[Provider],
+    order: &[usize],
+    settings: &ProbeSettings,
+) -> Result<Vec<Measurement>, Error> {
+    let next = AtomicUsize::new(0);
+    let last_start = Mutex::new(None::<Instant>);
+    let measured = Mutex::new(Vec::with_capacity(order.len()));
+    thread::scope(|scope| {
+        for _ in 0..settings.max_concurrent.min(order.len()) {
+            scope.spawn(|| {
+                while let Some(&index) = order.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    wait_turn(&last_start);
+                    let provider = &providers[index];
+                    let measurement =
+                        measure_latency(&provider.ip, &settings.for_provider(provider));
+                    measured.lock().unwrap().push((index, measurement));
+                }
+            });
+        }
+    });
+    let mut measured = measured.into_inner().unwrap();
+    measured.sort_by_key(|(index, _)| *index);
+    measured
+        .into_iter()
+        .map(|(_, measurement)| measurement)
+        .collect()
+}
+
+/// Measures providers one at a time in list order until one answers within
+/// `threshold`, for `--fail-fast`, and returns the results so far sorted by
+/// latency; the server that met the threshold is the fastest of them. When
+/// none does, every provider has been measured.
+fn benchmark_until<'a>(
+    providers: &'a [Provider],
+    settings: &ProbeSettings,
+    threshold: Duration,
+) -> Result<Vec<(&'a Provider, Measurement)>, Error> {
+    let mut results = Vec::new();
+    for (index, provider) in providers.iter().enumerate() {
+        let measurement = measure_latency(&provider.ip, &settings.for_provider(provider))?;
+        let fast_enough = !provider.baseline
+            && measurement
+                .latency()
+                .is_some_and(|latency| latency <= threshold);
+        results.push((provider, measurement));
+        if fast_enough {
+            log(&format!(
+                "{} answered within {:?}, skipping the other {} server(s)",
+                provider.name,
+                threshold,
+                providers.len() - index - 1
+            ));
+            rank_by_latency(&mut results, |_| Duration::ZERO);
+            trace::measurements(&results);
+            return Ok(results);
+        }
+    }
+    warn(&format!(
+        "no server answered within {:?}, picking the best of all",
+        threshold
+    ));
+    rank_by_latency(&mut results, |_| Duration::ZERO);
+    trace::measurements(&results);
+    Ok(results)
+}
+
+/// Pings the `--ping-baseline` host like a server, returning it with its
+/// measurement.
+fn ping_baseline(cli: &Cli, settings: &ProbeSettings) -> Option<(String, Measurement)> {
+    let host = cli.ping_baseline.as_ref()?;
+    let measurement = match &settings.mock {
+        Some(mock) => mock.measurement(host),
+        None => backend::backend(Method::Icmp)
+            .measure(host, settings)
+            .unwrap_or_else(|err| {
+                warn(&format!("could not ping baseline {}: {}", host, err));
+                Measurement::default()
+            }),
+    };
+    Some((host.clone(), measurement))
+}
+
+/// Shows the link's latency floor from [`ping_baseline`] next to the
+/// fastest server, which can be no faster than the link.
+fn report_baseline(
+    baseline: Option<(String, Measurement)>,
+    results: &[(&Provider, Measurement)],
+    numbers: NumberFormat,
+) {
+    let Some((host, measurement)) = baseline else {
+        return;
+    };
+    let Some(floor) = measurement.latency() else {
+        warn(&format!("baseline {} did not answer any ping", host));
+        return;
+    };
+    let fastest = results
+        .iter()
+        .filter_map(|(provider, measurement)| Some((provider, measurement.latency()?)))
+        .min_by_key(|(_, latency)| *latency);
+    log(&format!(
+        "\nNetwork baseline: {} to {}{}",
+        format_duration(floor, numbers),
+        host,
+        fastest
+            .map(|(provider, latency)| format!(
+                ", {} is {} above it",
+                provider.name,
+                format_duration(latency.saturating_sub(floor), numbers)
+            ))
+            .unwrap_or_default()
+    ));
+}
+
+/// Whether a server answered and can be queried directly, as the checks
+/// beyond latency do; the system resolver has no address to query.
+fn queryable(provider: &Provider, measurement: &Measurement) -> bool {
+    measurement.reachable() && !providers::is_system(&provider.ip)
+}
+
+/// Reports for every reachable server whether it blocks
+/// [`filtering::BLOCKED_NAME`], a typical ad/tracker domain, waiting
+/// `timeout` for each answer.
+fn report_filtering(results: &[(&Provider, Measurement)], timeout: Duration) {
+    log(&format!(
+        "\nChecking for filtering with {}...",
+        filtering::BLOCKED_NAME
+    ));
+    for (provider, measurement) in results {
+        if queryable(provider, measurement) {
+            let verdict = filtering::check(&provider.ip, timeout);
+            log(&format!("  {}: {}", provider.name, verdict));
+            trace::check(&provider.ip, "filtering", &verdict);
+        }
+    }
+}
+
+/// Checks whether every reachable server minimizes query names, see
+/// [`minimization::check`], keeping the verdicts in the measurements for the
+/// privacy column and warning about the servers that leak full names.
+/// Mocked runs have no real servers to check.
+fn check_minimization(results: &mut [(&Provider, Measurement)], settings: &ProbeSettings) {
+    if settings.mock.is_some() {
+        return;
+    }
+    log(&format!(
+        "\nChecking QNAME minimization with {}...",
+        minimization::TEST_NAME
+    ));
+    for (provider, measurement) in results.iter_mut() {
+        if !queryable(provider, measurement) {
+            continue;
+        }
+        let verdict = minimization::check(&provider.ip, settings.timeout);
+        trace::check(&provider.ip, "qname minimization", &verdict);
+        match verdict {
+            minimization::Minimization::Leaks => {
+                warn(&format!("{} {}", provider.name, verdict));
+                measurement.minimizes = Some(false);
+            }
+            minimization::Minimization::Minimizes => {
+                log(&format!("  {}: {}", provider.name, verdict));
+                measurement.minimizes = Some(true);
+            }
+            minimization::Minimization::Unknown(_) => {
+                log(&format!("  {}: {}", provider.name, verdict))
+            }
+        }
+    }
+}
+
+/// Reports for every reachable server how it delivers a large response, see
+/// [`truncation::check`].
+fn report_large_responses(
+    results: &[(&Provider, Measurement)],
+    timeout: Duration,
+) -> HashMap<String, truncation::Delivery> {
+    log("\nChecking large responses (root DNSKEY with DNSSEC)...");
+    let mut deliveries = HashMap::new();
+    for (provider, measurement) in results {
+        if !queryable(provider, measurement) {
+            continue;
+        }
+        match truncation::check(&provider.ip, timeout) {
+            Ok((delivery, elapsed)) => {
+                match &delivery {
+                    truncation::Delivery::Truncated(_) => {
+                        warn(&format!("{}: {}", provider.name, delivery))
+                    }
+                    _ => log(&format!(
+                        "  {}: {} in {:.2?}",
+                        provider.name, delivery, elapsed
+                    )),
+                }
+                trace::check(
+                    &provider.ip,
+                    "large responses",
+                    format!("{} in {:.2?}", delivery, elapsed),
+                );
+                deliveries.insert(provider.ip.clone(), delivery);
+            }
+            Err(err) => {
+                log(&format!("  {}: unknown ({})", provider.name, err));
+                trace::check(
+                    &provider.ip,
+                    "large responses",
+                    format!("unknown ({})", err),
+                );
+            }
+        }
+    }
+    deliveries
+}
+
+/// Grades every server that answered for `--health` and logs the grades in
+/// the order of the results. `incorrect` are the servers that failed the
+/// correctness checks, which mocked runs skip, and `deliveries` the
+/// outcomes of `--check-large-responses`.
+fn report_health(
+    results: &[(&Provider, Measurement)],
+    incorrect: &[String],
+    deliveries: &HashMap<String, truncation::Delivery>,
+    settings: &ProbeSettings,
+    cli: &Cli,
+) -> HashMap<String, health::Health> {
+    let weights = cli.health_weights.clone().unwrap_or_default();
+    let checked = settings.mock.is_none();
+    log("\nHealth:");
+    let mut grades = HashMap::new();
+    for (provider, measurement) in results {
+        if !measurement.reachable() {
+            continue;
+        }
+        let checks = health::Checks {
+            correct: checked.then(|| !incorrect.contains(&provider.ip)),
+            delivery: deliveries.get(&provider.ip),
+        };
+        let health = health::score(measurement, &checks, &weights);
+        trace::check(&provider.ip, "health", &health);
+        log(&format!(
+            "  {}: {} ({})",
+            provider.name,
+            health.score,
+            health.grade()
+        ));
+        grades.insert(provider.ip.clone(), health);
+    }
+    grades
+}
+
+/// Resolves the probe domain on every reachable server and warns about the
+/// ones whose answer has nothing in common with the consensus, see
+/// [`consistency::diverges`].
+fn report_consistency(results: &[(&Provider, Measurement)], name: &str, timeout: Duration) {
+    log(&format!("\nComparing answers for {}...", name));
+    let answers: Vec<(&Provider, Result<Vec<IpAddr>, String>)> = results
+        .iter()
+        .filter(|(provider, measurement)| queryable(provider, measurement))
+        .map(|(provider, _)| (*provider, consistency::answers(&provider.ip, name, timeout)))
+        .collect();
+    let addresses: Vec<&[IpAddr]> = answers
+        .iter()
+        .filter_map(|(_, answer)| answer.as_deref().ok())
+        .collect();
+    let consensus = consistency::consensus(&addresses);
+    if consensus.is_empty() {
+        warn(&format!("no server resolved {}, nothing to compare", name));
+        return;
+    }
+    let list = |ips: &[IpAddr]| {
+        ips.iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    log(&format!("  consensus: {}", list(&consensus)));
+    let mut diverging = 0;
+    for (provider, answer) in &answers {
+        match answer {
+            Ok(answer) if consistency::diverges(answer, &consensus) => {
+                diverging += 1;
+                trace::check(
+                    &provider.ip,
+                    "consistency",
+                    format!("diverges: [{}]", list(answer)),
+                );
+                warn(&format!(
+                    "{} diverges from the consensus: {}",
+                    provider.name,
+                    if answer.is_empty() {
+                        "no addresses".to_string()
+                    } else {
+                        list(answer)
+                    }
+                ));
+            }
+            Ok(answer) => {
+                log(&format!("  {}: {}", provider.name, list(answer)));
+                trace::check(&provider.ip, "consistency", format!("[{}]", list(answer)));
+            }
+            Err(err) => {
+                log(&format!("  {}: unknown ({})", provider.name, err));
+                trace::check(&provider.ip, "consistency", format!("unknown ({})", err));
+            }
+        }
+    }
+    if diverging == 0 {
+        log("  every server agrees");
+    }
+}
+
+/// Probes the cache behavior of every answering server and logs each one's
+/// hit ratio over the session so far, see [`CacheStats::update`].
+fn report_cache_stats(
+    results: &[(&Provider, Measurement)],
+    cache: &mut CacheStats,
+    name: &str,
+    timeout: Duration,
+    rng: &mut Rng,
+) {
+    cache.update(results, name, timeout, rng);
+    log("\nCache hit ratio this session:");
+    for (provider, _) in results {
+        let Some(tally) = cache.tally(&provider.ip) else {
+            continue;
+        };
+        match (tally.ratio(), tally.warm, tally.cold) {
+            (Some(ratio), Some(warm), Some(cold)) => log(&format!(
+                "  {}: {:.0}% of {} answers (cached {:.2?}, uncached {:.2?})",
+                provider.name,
+                ratio * 100.0,
+                tally.total,
+                warm,
+                cold
+            )),
+            _ => log(&format!(
+                "  {}: cached and uncached answers are too close to tell apart",
+                provider.name
+            )),
+        }
+    }
+}
+
+/// Blocks until [`PROBE_SPACING`] has passed since the last server's
+/// measurement started, then records this start.
+fn wait_turn(last_start: &Mutex<Option<Instant>>) {
+    let mut last_start = last_start.lock().unwrap();
+    if let Some(ready) = last_start.map(|previous| previous + PROBE_SPACING) {
+        thread::sleep(ready.saturating_duration_since(Instant::now()));
+    }
+    *last_start = Some(Instant::now());
+}
+
+/// Removes the candidates with fewer than `min_samples` answered probes
+/// (`--min-samples`), whose latency rests on too few answers to trust.
+fn drop_unreliable(
+    candidates: &mut Vec<&Provider>,
+    results: &[(&Provider, Measurement)],
+    min_samples: Option<usize>,
+) {
+    let Some(min_samples) = min_samples else {
+        return;
+    };
+    candidates.retain(|provider| {
+        let Some((_, measurement)) = results.iter().find(|(other, _)| other.ip == provider.ip)
+        else {
+            return false;
+        };
+        let reliable = measurement.samples.len() >= min_samples;
+        if !reliable {
+            warn(&format!(
+                "{} is unreliable, only {} of {} probes answered (--min-samples {}), not applying it",
+                provider.name,
+                measurement.samples.len(),
+                measurement.sent,
+                min_samples
+            ));
+            trace::filtered(
+                &provider.ip,
+                format!(
+                    "only {} of {} probes answered (--min-samples {})",
+                    measurement.samples.len(),
+                    measurement.sent,
+                    min_samples
+                ),
+            );
+        }
+        reliable
+    });
+}
+
+/// Runs the [`correctness`] checks on every reachable server, warns about
+/// those that answer wrongly (NXDOMAIN hijacking, no address for the probe
+/// domain) and returns their addresses. Mocked runs have no real servers to
+/// check.
+fn detect_incorrect(
+    results: &[(&Provider, Measurement)],
+    settings: &ProbeSettings,
+    rng: &mut Rng,
+) -> Vec<String> {
+    if settings.mock.is_some() {
+        return Vec::new();
+    }
+    results
+        .iter()
+        .filter(|(provider, measurement)| queryable(provider, measurement))
+        .filter_map(|(provider, _)| {
+            let verdict = correctness::check(
+                &provider.ip,
+                &settings.probe_name,
+                &settings.for_provider(provider),
+                rng,
+            );
+            trace::check(&provider.ip, "correctness", &verdict);
+            match verdict {
+                correctness::Verdict::Incorrect(_) => {
+                    warn(&format!("{} {}", provider.name, verdict));
+                    Some(provider.ip.clone())
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Takes the servers that do not answer DNS over TLS out of `candidates`, as
+/// a `--apply-with profile` profile sends every query over it. Servers ranked
+/// over DoT already answered it, and mocked runs have nothing to probe.
+fn drop_without_dot(candidates: &mut Vec<&Provider>, settings: &ProbeSettings) {
+    if settings.mock.is_some() || (!settings.fallback && settings.methods[0] == Method::Dot) {
+        return;
+    }
+    let probe = ProbeSettings {
+        count: 1,
+        warmup: 0,
+        record_types: Vec::new(),
+        log_pop: false,
+        ..settings.clone()
+    };
+    candidates.retain(|provider| {
+        let answers = backend::backend(Method::Dot)
+            .measure(&provider.ip, &probe.for_provider(provider))
+            .is_ok_and(|measurement| measurement.reachable());
+        if !answers {
+            log(&format!(
+                "{} does not answer DNS over TLS, skipping it for the profile",
+                provider.name
+            ));
+            trace::filtered(&provider.ip, "no answer over DNS over TLS");
+        }
+        answers
+    });
+}
+
+/// Takes the servers that failed the correctness checks out of `candidates`.
+fn drop_incorrect(candidates: &mut Vec<&Provider>, incorrect: &[String]) {
+    candidates.retain(|provider| {
+        let correct = !incorrect.contains(&provider.ip);
+        if !correct {
+            trace::filtered(&provider.ip, "answers incorrectly");
+        }
+        correct
+    });
+}
+
+/// The changes applying `servers` makes, with the `--split-dns` and
+/// `--remove-split-dns` ones. `previous` are the manual servers from before
+/// the run, restored if `servers` do not stick.
+fn planned_changes<'a>(cli: &'a Cli, servers: &'a [String], previous: &'a [String]) -> Changes<'a> {
+    Changes {
+        servers,
+        split_dns: cli
+            .split_dns
+            .iter()
+            .map(|split| (split.domain.as_str(), &split.servers[..]))
+            .collect(),
+        remove_split_dns: &cli.remove_split_dns,
+        previous: Some(previous),
+    }
+}
+
+/// Asks on the terminal whether to apply `chosen`, unless `--yes` or
+/// `--watch` was given. Without a terminal to ask on, `--unattended` decides.
+fn confirm_apply(cli: &Cli, chosen: &Provider) -> bool {
+    if cli.yes || cli.watch.is_some() {
+        return true;
+    }
+    if !(io::stdin().is_terminal() && system::is_interactive()) {
+        return match cli.unattended {
+            Unattended::Apply => true,
+            Unattended::DryRun => {
+                log("No terminal to confirm on and --unattended dry-run given");
+                false
+            }
+        };
+    }
+    eprint!("\nApply {} ({})? [y/N] ", chosen.name, chosen.ip);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Asks every candidate which POP answers, logs it and moves the best
+/// candidate in `region` to the front if it is at most
+/// [`pop::REGION_MARGIN`] slower than the best one overall.
+///
+/// Returns the POPs of the candidates that reported one, by address.
+fn prefer_region(
+    candidates: &mut Vec<&Provider>,
+    results: &[(&Provider, Measurement)],
+    region: &str,
+    timeout: Duration,
+) -> HashMap<String, String> {
+    log(&format!(
+        "\nLooking up the POPs answering, preferring {}...",
+        region
+    ));
+    let pops: HashMap<String, String> = candidates
+        .iter()
+        .filter_map(|provider| {
+            let found = pop::identify(&provider.ip, timeout)?;
+            log(&format!("  {}: {}", provider.name, found));
+            trace::check(&provider.ip, "pop", &found);
+            Some((provider.ip.clone(), found))
+        })
+        .collect();
+    let latency = |provider: &Provider| {
+        results
+            .iter()
+            .find(|(other, _)| other.ip == provider.ip)
+            .and_then(|(_, measurement)| measurement.latency())
+    };
+    let in_region = |provider: &Provider| {
+        pops.get(&provider.ip)
+            .is_some_and(|found| pop::in_region(found, region))
+    };
+    let Some(best) = candidates.first().and_then(|provider| latency(provider)) else {
+        return pops;
+    };
+    if let Some(index) = candidates.iter().position(|provider| {
+        in_region(provider) && latency(provider).is_some_and(|own| own <= best + pop::REGION_MARGIN)
+    }) {
+        if index > 0 {
+            let preferred = candidates.remove(index);
+            log(&format!(
+                "Preferring {} as it answers from {}",
+                preferred.name, region
+            ));
+            trace::outcome(&format!(
+                "preferred {} as it answers from {}",
+                preferred.ip, region
+            ));
+            candidates.insert(0, preferred);
+        }
+    } else if !pops.is_empty() {
+        warn(&format!(
+            "no server close to the fastest answers from {}",
+            region
+        ));
+    }
+    pops
+}
+
+/// Picks the server applied after `primary` with `--servers 2` from the
+/// ranked `candidates`.
+///
+/// With `diverse`, the fastest server of another provider family is preferred
+/// so one provider's outage cannot take out both, falling back to the next
+/// fastest server when no other family answered.
+fn pick_secondary<'a>(
+    candidates: &[&'a Provider],
+    primary: &Provider,
+    diverse: bool,
+) -> Option<&'a Provider> {
+    let mut candidates = candidates
+        .iter()
+        .filter(|provider| provider.ip != primary.ip)
+        .copied();
+    if !diverse {
+        return candidates.next();
+    }
+    let candidates: Vec<&Provider> = candidates.collect();
+    let other_family = candidates
+        .iter()
+        .find(|provider| provider.family != primary.family);
+    if other_family.is_none() && !candidates.is_empty() {
+        warn(&format!(
+            "only {} servers answered, secondary is from the same provider",
+            primary.family
+        ));
+    }
+    other_family.or(candidates.first()).copied()
+}
+
+/// Fails with [`Error::NoNetwork`] when not a single server answered, which
+/// means the machine is offline rather than every provider being down.
+fn check_connectivity(results: &[(&Provider, Measurement)]) -> Result<(), Error> {
+    if results
+        .iter()
+        .any(|(provider, measurement)| !provider.baseline && measurement.reachable())
+    {
+        Ok(())
+    } else {
+        Err(Error::NoNetwork)
+    }
+}
+
+/// Runs `measure` again when the default route changed meanwhile (sleep and
+/// wake, a VPN coming up or going down), as its numbers would then mix two
+/// networks. Returns the results with the route they were measured on, or
+/// [`Error::NetworkChanged`] when the route moved again on the last of
+/// [`ROUTE_RESTARTS`] restarts.
+fn on_stable_route<T>(
+    mut measure: impl FnMut() -> Result<T, Error>,
+) -> Result<(T, Option<Route>), Error> {
+    let mut restarts = 0;
+    loop {
+        let before = system::default_route();
+        let results = measure()?;
+        let after = system::default_route();
+        if before == after {
+            return Ok((results, after));
+        }
+        if restarts == ROUTE_RESTARTS {
+            return Err(network_changed(&before, &after));
+        }
+        restarts += 1;
+        warn(&format!(
+            "the network changed from {} to {} during the measurement, measuring again",
+            describe_route(&before),
+            describe_route(&after)
+        ));
+    }
+}
+
+/// Fails with [`Error::NetworkChanged`] when the default route is no longer
+/// the one the results were `measured_on`.
+fn ensure_same_route(measured_on: &Option<Route>) -> Result<(), Error> {
+    let now = system::default_route();
+    if &now == measured_on {
+        Ok(())
+    } else {
+        Err(network_changed(measured_on, &now))
+    }
+}
+
+fn network_changed(before: &Option<Route>, after: &Option<Route>) -> Error {
+    Error::NetworkChanged {
+        before: describe_route(before),
+        after: describe_route(after),
+    }
+}
+
+fn describe_route(route: &Option<Route>) -> String {
+    route
+        .as_ref()
+        .map_or("no network".to_string(), Route::to_string)
+}
+
+/// Columns of the results table: `--columns`, plus the description with
+/// `--describe`.
+fn table_columns(cli: &Cli) -> Vec<Column> {
+    let mut columns = cli.columns.clone();
+    if cli.describe && !columns.contains(&Column::Description) {
+        columns.push(Column::Description);
+    }
+    columns
+}
+
+/// Writes the HTML report if `--html` was given, highlighting `chosen`, the
+/// address of the server picked to apply.
+fn write_html_report(
+    results: &[(&Provider, Measurement)],
+    chosen: Option<&str>,
+    cli: &Cli,
+) -> Result<(), Error> {
+    let Some(path) = &cli.html else {
+        return Ok(());
+    };
+    write_html(path, results, chosen, cli.precision).map_err(|err| Error::Write {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    })?;
+    log(&format!("Wrote HTML report to {}", path.display()));
+    Ok(())
+}
+
+/// Benchmarks the resolvers serving a single scope without touching the
+/// system configuration.
+///
+/// Split-DNS resolvers answer for private domains that public providers
+/// cannot resolve, so the scope's nameservers are only measured and
+/// reported, never replaced.
+fn benchmark_scope(scope: &str, cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    log(&format!(
+        "\nChecking DNS configuration for scope {}...",
+        scope
+    ));
+    warn_about_proxies();
+    let resolvers = get_scope_resolvers(scope);
+    if resolvers.is_empty() {
+        log(&format!("No resolver found for scope {}", scope));
+        return Ok(());
+    }
+
+    let description = format!("Resolver for {}", scope);
+    let mut providers: Vec<Provider> = Vec::new();
+    for resolver in &resolvers {
+        let name = match &resolver.interface {
+            Some(interface) => format!("{} via {}", scope, interface),
+            None => scope.to_string(),
+        };
+        for ip in &resolver.nameservers {
+            log(&format!("  • {} ({})", ip, name));
+            providers.push(Provider::new(&name, ip, &description));
+        }
+    }
+
+    let providers = prepare_providers(providers, cli, rng);
+    let settings = ProbeSettings::from_cli(cli);
+    log_configuration(cli, &settings, &providers, None);
+    log("\nStarting DNS latency tests...");
+    let results = benchmark(&providers, &settings, rng)?;
+    print_results(
+        &results,
+        cli.format,
+        &table_columns(cli),
+        NumberFormat::from_cli(cli),
+    );
+    write_html_report(&results, None, cli)
+}
+
+/// Measures only the currently configured DNS servers, with more probes than
+/// a full sweep, and reports latency, jitter and loss without changing
+/// anything.
+///
+/// Servers from the built-in list are shown under their provider name.
+fn current_only(cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    log("\nChecking current DNS configuration...");
+    let known = builtin_providers();
+    let providers: Vec<Provider> = print_current_dns()
+        .iter()
+        .map(|ip| {
+            let name = known
+                .iter()
+                .find(|provider| &provider.ip == ip)
+                .map_or(ip.as_str(), |provider| provider.name.as_str());
+            Provider::new(name, ip, "Current DNS server")
+        })
+        .collect();
+    if providers.is_empty() {
+        log("No DNS servers configured, nothing to test");
+        return Ok(());
+    }
+
+    let settings = ProbeSettings {
+        count: CURRENT_PING_COUNT,
+        ..ProbeSettings::from_cli(cli)
+    };
+    log_configuration(cli, &settings, &providers, None);
+    log("\nStarting DNS latency tests...");
+    let results = benchmark(&providers, &settings, rng)?;
+    let mut columns = table_columns(cli);
+    for column in [Column::Jitter, Column::Loss] {
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    print_results(&results, cli.format, &columns, NumberFormat::from_cli(cli));
+    write_html_report(&results, None, cli)?;
+    check_connectivity(&results)
+}
+
+/// Benchmarks the providers and prints a ranked table.
+///
+/// Never reads or writes the system DNS configuration, so it also works in
+/// containers and on systems without `scutil`/`networksetup`; only
+/// `--compare-to-automatic` asks for the DHCP lease.
+fn bench(cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    let mut providers = prepare_providers(load_providers(cli), cli, rng);
+    let automatic = automatic_servers(cli);
+    add_automatic_servers(&mut providers, &automatic);
+    if cli.compare_to_system {
+        providers.push(providers::system_resolver());
+    }
+    let settings = ProbeSettings::from_cli(cli);
+    log_configuration(cli, &settings, &providers, None);
+    log("\nStarting DNS latency tests...");
+    let baseline = ping_baseline(cli, &settings);
+    let mut results = benchmark(&providers, &settings, rng)?;
+    record_history(&results, cli);
+    if cli.check_qname_minimization {
+        check_minimization(&mut results, &settings);
+    }
+    print_results(
+        &results,
+        cli.format,
+        &table_columns(cli),
+        NumberFormat::from_cli(cli),
+    );
+    report_baseline(baseline, &results, NumberFormat::from_cli(cli));
+    report_automatic(&automatic, &results, NumberFormat::from_cli(cli));
+    if cli.check_filtering {
+        report_filtering(&results, settings.timeout);
+    }
+    if cli.check_consistency {
+        report_consistency(&results, &cli.probe_domain, settings.timeout);
+    }
+    let deliveries = if cli.check_large_responses {
+        report_large_responses(&results, settings.timeout)
+    } else {
+        HashMap::new()
+    };
+    let incorrect = detect_incorrect(&results, &settings, rng);
+    if cli.health {
+        report_health(&results, &incorrect, &deliveries, &settings, cli);
+    }
+    write_html_report(&results, None, cli)?;
+    check_connectivity(&results)
+}
+
+/// Appends a run to the history log under `--tag`. Failing to write it only
+/// warns, the run itself went fine. Mocked runs are left out, their numbers
+/// did not come from the network.
+fn record_history(results: &[(&Provider, Measurement)], cli: &Cli) {
+    if cli.mock_latencies.is_some() {
+        return;
+    }
+    if let Err(err) = history::record(results, cli.tag.as_deref(), cli.history_limit) {
+        warn(&format!("history not updated: {}", err));
+    }
+}
+
+/// Prints how each server's latency changed between the latest runs tagged
+/// `before` and `after`, e.g. before and after switching ISPs.
+fn compare_tags(before: &str, after: &str) -> Result<(), Error> {
+    let runs = history::load();
+    let find =
+        |tag: &str| history::latest_tagged(&runs, tag).ok_or(Error::UnknownTag(tag.to_string()));
+    let (old, new) = (find(before)?, find(after)?);
+    log(&format!(
+        "Comparing {} ({}) with {} ({})",
+        before, old.timestamp, after, new.timestamp
+    ));
+
+    let latency = |entry: Option<&history::Entry>| entry.and_then(|entry| entry.latency_ms);
+    let cell = |ms: Option<f64>| ms.map_or("-".to_string(), |ms| format!("{:.2}ms", ms));
+    let mut rows = vec![[
+        "Name".to_string(),
+        "Address".to_string(),
+        before.to_string(),
+        after.to_string(),
+        "Change".to_string(),
+    ]];
+    let mut seen = Vec::new();
+    for entry in old.results.iter().chain(&new.results) {
+        if seen.contains(&&entry.ip) {
+            continue;
+        }
+        seen.push(&entry.ip);
+        let lookup = |run: &'_ history::Run| latency(run.results.iter().find(|e| e.ip == entry.ip));
+        let (was, is) = (lookup(old), lookup(new));
+        let change = match (was, is) {
+            (Some(was), Some(is)) => format!("{:+.2}ms", is - was),
+            _ => "-".to_string(),
+        };
+        rows.push([
+            entry.name.clone(),
+            entry.ip.clone(),
+            cell(was),
+            cell(is),
+            change,
+        ]);
+    }
+
+    let widths = column_widths(&rows);
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(index, (value, width))| match index {
+                0 | 1 => format!("{:<width$}", value, width = width),
+                _ => format!("{:>width$}", value, width = width),
+            })
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    Ok(())
+}
+
+/// Measures like [`bench`] but prints nothing on stdout except a shell
+/// assignment of the winner, e.g. `WISP_FASTEST_DNS=1.1.1.1`, for
+/// `eval "$(wisp --eval)"`. Servers failing the correctness checks are
+/// passed over unless `--allow-incorrect`.
+fn eval_winner(cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    let providers = prepare_providers(load_providers(cli), cli, rng);
+    let settings = ProbeSettings::from_cli(cli);
+    log_configuration(cli, &settings, &providers, None);
+    log("\nStarting DNS latency tests...");
+    let mut results = benchmark(&providers, &settings, rng)?;
+    record_history(&results, cli);
+    check_connectivity(&results)?;
+    let strategy = selection::from_cli(cli, Vec::new());
+    let mut candidates = strategy.select(&mut results);
+    trace::ranking(
+        strategy.describe(),
+        &candidates,
+        &results,
+        cli.score_expr.as_ref(),
+    );
+    drop_unreliable(&mut candidates, &results, cli.min_samples);
+    let incorrect = detect_incorrect(&results, &settings, rng);
+    if !cli.allow_incorrect {
+        drop_incorrect(&mut candidates, &incorrect);
+    }
+    let winner = candidates.first().ok_or(Error::NoResponse)?;
+    trace::winner(winner, std::slice::from_ref(&winner.ip));
+    println!("WISP_FASTEST_DNS={}", winner.ip);
+    Ok(())
+}
+
+/// Measures two or more servers head to head with a larger sample count and
+/// reports which one is faster, and how confidently.
+///
+/// The two fastest servers are compared with Welch's t-test; nothing on the
+/// system is changed.
+fn compare(addresses: &[String], cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    let providers: Vec<Provider> = addresses
+        .iter()
+        .map(|address| Provider::new(address, address, "Compared server"))
+        .collect();
+    let providers = prepare_providers(providers, cli, rng);
+    let settings = ProbeSettings::from_cli(cli);
+    // At least as many probes as a sweep, more when --samples asks for them
+    let settings = ProbeSettings {
+        count: settings.count.max(COMPARE_PING_COUNT),
+        ..settings
+    };
+    log_configuration(cli, &settings, &providers, None);
+
+    log(&format!(
+        "\nComparing {} servers with {} pings each...",
+        providers.len(),
+        settings.count
+    ));
+    let results = benchmark(&providers, &settings, rng)?;
+    print_results(
+        &results,
+        cli.format,
+        &table_columns(cli),
+        NumberFormat::from_cli(cli),
+    );
+    write_html_report(&results, None, cli)?;
+
+    for (provider, measurement) in &results {
+        if let (Some(latency), Some(std_dev)) = (measurement.latency(), measurement.std_dev()) {
+            log(&format!(
+                "  {}: {} ± {} over {} replies",
+                provider.name,
+                format_duration(latency, NumberFormat::from_cli(cli)),
+                format_duration(std_dev, NumberFormat::from_cli(cli)),
+                measurement.samples.len()
+            ));
+        }
+    }
+
+    let reachable: Vec<&(&Provider, Measurement)> = results
+        .iter()
+        .filter(|(_, measurement)| measurement.reachable())
+        .collect();
+    let [(winner, best), (runner_up, second), ..] = reachable[..] else {
+        return Err(Error::NoResponse);
+    };
+    let (Some(best_latency), Some(second_latency)) = (best.latency(), second.latency()) else {
+        return Err(Error::NoResponse);
+    };
+    let verdict = match stats::welch_p_value(&best.samples, &second.samples) {
+        Some(p) if p < 0.01 => format!("high confidence (p = {:.3})", p),
+        Some(p) if p < 0.05 => format!("moderate confidence (p = {:.3})", p),
+        Some(p) => format!(
+            "not statistically significant (p = {:.2}), treat them as equally fast",
+            p
+        ),
+        None => "too few replies to judge confidence".to_string(),
+    };
+    log(&format!(
+        "\nWinner: {} is {} faster than {}, {}",
+        winner.name,
+        format_duration(second_latency - best_latency, NumberFormat::from_cli(cli)),
+        runner_up.name,
+        verdict
+    ));
+    Ok(())
 }
 
 /// The optimization process follows these steps:
@@ -121,86 +1895,587 @@ fn print_current_dns() {
 /// 4. Print test results
 /// 5. Configure the fastest DNS server
 /// 6. Display final DNS configuration
+/// 7. Summarize the change, optionally as a notification
 ///
 /// # Notes
-/// * The process tests multiple DNS providers including Cloudflare, Google, Quad9, etc.
+/// * The process tests the [`builtin_providers`], including Cloudflare, Google, Quad9, etc.,
+///   or the list given with `--providers`
 /// * Each provider's primary and secondary servers are tested
-/// * Results are sorted by latency
-/// * The fastest DNS server is automatically configured
-fn main() {
-    log("=== DNS Optimization Tool ===");
+/// * Results are sorted by latency, servers that never answer are not selected
+/// * The `--include-local` baseline is measured and shown but never selected
+/// * Servers that resolve nonexistent domains (NXDOMAIN hijacking) or fail to
+///   resolve the probe domain are flagged, and passed over for the next
+///   fastest correct one unless `--allow-incorrect` is given
+/// * `--strategy` decides how results are ranked for the choice, see
+///   [`selection`]; with `--sticky`, the current servers get a latency bonus
+///   so the tool only switches for a clearly better alternative
+/// * `--split-dns` domains get their own servers through resolver files,
+///   on top of the system-wide choice
+/// * The fastest DNS server is automatically configured; with `--servers 2` a
+///   secondary from a different provider family is added (`--no-diverse`
+///   takes the second fastest server instead)
+/// * Without a default route, or when no server answers at all, the run stops
+///   with [`Error::NoNetwork`] and the original configuration is put back
+/// * When no servers are set manually, step 2 is skipped as there is nothing
+///   to reset
+/// * With `--fail-fast`, servers are tested in list order only until one
+///   meets `--threshold`, see [`benchmark_until`]
+/// * With `--keep-if-best`, the current servers are measured too and step 2
+///   is skipped; when one of them wins, nothing is changed at all
+/// * With `--prepend`, step 2 is skipped and the servers configured before
+///   the run are applied after the chosen one, except tested providers that
+///   lost the ranking, so repeated runs do not pile up old winners
+/// * Before step 5 an interactive run asks for confirmation (`--yes` skips
+///   it, `--unattended` decides without a terminal); declining restores the
+///   original configuration
+/// * With `--safe`, any failure after step 1 restores the original configuration
+/// * Unless `apply` is set, steps 2 and 5-7 are skipped and the chosen server
+///   is only logged
+/// * With `cache`, the cache behavior of every answering server is probed
+///   and the session's hit ratios are logged, see [`report_cache_stats`]
+/// * With `smoothing`, servers are ranked on their moving average latency
+///   (see [`Ewma::update`]), measured with a single ping once it is seeded
+fn optimize(
+    cli: &Cli,
+    apply: bool,
+    smoothing: Option<&mut Ewma>,
+    cache: Option<&mut CacheStats>,
+    rng: &mut Rng,
+) -> Result<(), Error> {
+    let mut providers = prepare_providers(load_providers(cli), cli, rng);
 
     // 1. Show current DNS
     log("\nChecking current DNS configuration...");
-    print_current_dns();
+    let original_dns = print_current_dns();
+    if cli.keep_if_best {
+        add_current_servers(&mut providers, &original_dns);
+    }
+    let automatic = automatic_servers(cli);
+    add_automatic_servers(&mut providers, &automatic);
+    if cli.compare_to_system {
+        providers.push(providers::system_resolver());
+    }
+    warn_about_proxies();
+    if cli.mock_latencies.is_none() && !has_default_route() {
+        return Err(Error::NoNetwork);
+    }
+    // Guards the reset until some server answered, and the whole run with --safe
+    let (rollback, manual_dns) = if apply {
+        let manual_dns = get_manual_dns()?;
+        let rollback = RollbackGuard::new(manual_dns.clone());
 
-    // 2. Set to automatic
-    log("\nResetting to automatic DNS...");
-    set_dns_automatic();
+        // 2. Set to automatic; the existing servers stay in use with --prepend,
+        // and with --keep-if-best until they lost. A profile overrides them
+        // anyway, so they are left alone for one.
+        if cli.print_commands {
+            log("\nOnly printing the commands, testing with the current DNS");
+        } else if !cli.prepend && !cli.keep_if_best && cli.apply_with == ApplyWith::Networksetup {
+            if manual_dns.is_empty() {
+                log("\nAlready on automatic, skipping reset");
+            } else {
+                log("\nResetting to automatic DNS...");
+                set_dns_automatic()?;
+                trace::outcome("reset to automatic DNS for the measurement");
+            }
+        }
+        (Some(rollback), manual_dns)
+    } else {
+        (None, Vec::new())
+    };
 
     // 3. Test various DNS servers
-    let dns_servers = [
-        // Cloudflare - Known for speed and privacy
-        ("Cloudflare Primary", "1.1.1.1"),
-        ("Cloudflare Secondary", "1.0.0.1"),
-        // Google - Most popular, highly reliable
-        ("Google Primary", "8.8.8.8"),
-        ("Google Secondary", "8.8.4.4"),
-        // Quad9 - Security focused, blocks malicious domains
-        ("Quad9 Primary", "9.9.9.9"),
-        ("Quad9 Secondary", "149.112.112.112"),
-        // OpenDNS - Cisco owned, extensive filtering
-        ("OpenDNS Primary", "208.67.222.222"),
-        ("OpenDNS Secondary", "208.67.220.220"),
-        // AdGuard - Ad blocking, no logging
-        ("AdGuard Primary", "94.140.14.14"),
-        ("AdGuard Secondary", "94.140.15.15"),
-        // CleanBrowsing - Family friendly filtering
-        ("CleanBrowsing Primary", "185.228.168.9"),
-        ("CleanBrowsing Secondary", "185.228.169.9"),
-        // Level3/CenturyLink - Enterprise grade
-        ("Level3 Primary", "4.2.2.1"),
-        ("Level3 Secondary", "4.2.2.2"),
-        // Comodo Secure - Security focused
-        ("Comodo Primary", "8.26.56.26"),
-        ("Comodo Secondary", "8.20.247.20"),
-        // Verisign - Enterprise reliability
-        ("Verisign Primary", "64.6.64.6"),
-        ("Verisign Secondary", "64.6.65.6"),
-        // NextDNS - Cloud-based, customizable
-        ("NextDNS", "45.90.28.167"),
-    ];
-
     log("\nStarting DNS latency tests...");
-    let mut latencies: Vec<_> = dns_servers
-        .iter()
-        .map(|(name, ip)| {
-            let latency = measure_latency(ip);
-            (name, ip, latency)
-        })
-        .collect();
-
-    latencies.sort_by_key(|&(_, _, latency)| latency);
+    let mut settings = ProbeSettings::from_cli(cli);
+    let smoothed = smoothing.as_ref().is_some_and(|ewma| !ewma.is_empty());
+    if smoothed {
+        settings.count = SMOOTHED_PING_COUNT;
+        settings.warmup = 0;
+    }
+    log_configuration(cli, &settings, &providers, Some(SERVICE));
+    let baseline = ping_baseline(cli, &settings);
+    let (mut results, route) = on_stable_route(|| match cli.threshold {
+        Some(ms) if cli.fail_fast => {
+            benchmark_until(&providers, &settings, Duration::from_millis(ms))
+        }
+        _ => benchmark(&providers, &settings, rng),
+    })?;
+    record_history(&results, cli);
+    if let Some(ewma) = smoothing {
+        ewma.update(&mut results);
+        log("Ranking on smoothed latencies");
+    }
+    if SHUTDOWN.load(Ordering::SeqCst) {
+        // Dropping the armed rollback puts the original servers back
+        log("Shutdown requested, abandoning this run before applying anything");
+        trace::outcome("shutdown requested, nothing applied");
+        return Ok(());
+    }
+    check_connectivity(&results)?;
+    let rollback = match rollback {
+        Some(rollback) if !cli.safe => {
+            rollback.disarm();
+            None
+        }
+        rollback => rollback,
+    };
+    let strategy = selection::from_cli(cli, original_dns.clone());
+    if selection::strategy(cli) != Strategy::Fastest
+        || cli.rank_percentile.is_some()
+        || cli.score_expr.is_some()
+    {
+        log(&format!("Ranking by {}", strategy.describe()));
+    }
+    let mut candidates = strategy.select(&mut results);
+    trace::ranking(
+        strategy.describe(),
+        &candidates,
+        &results,
+        cli.score_expr.as_ref(),
+    );
+    // Smoothed cycles send fewer probes than --min-samples may ask for; the
+    // history behind the moving average makes up for them
+    let min_samples = cli.min_samples.map(|min| {
+        if smoothed {
+            min.min(settings.count)
+        } else {
+            min
+        }
+    });
+    drop_unreliable(&mut candidates, &results, min_samples);
+    if cli.check_qname_minimization {
+        check_minimization(&mut results, &settings);
+    }
 
     // 4. Print results
-    log("\nLatency Test Results:");
-    println!("{:-<50}", "");
-    for (name, ip, latency) in &latencies {
-        println!("{:12} ({:10}) : {:.2?}", name, ip, latency);
+    print_results(
+        &results,
+        cli.format,
+        &table_columns(cli),
+        NumberFormat::from_cli(cli),
+    );
+    report_baseline(baseline, &results, NumberFormat::from_cli(cli));
+    report_automatic(&automatic, &results, NumberFormat::from_cli(cli));
+    if cli.check_filtering {
+        report_filtering(&results, settings.timeout);
+    }
+    if cli.check_consistency {
+        report_consistency(&results, &cli.probe_domain, settings.timeout);
     }
-    println!("{:-<50}", "");
+    let deliveries = if cli.check_large_responses {
+        report_large_responses(&results, settings.timeout)
+    } else {
+        HashMap::new()
+    };
+    if let Some(cache) = cache {
+        report_cache_stats(&results, cache, &cli.probe_domain, settings.timeout, rng);
+    }
+    let incorrect = detect_incorrect(&results, &settings, rng);
+    let grades = if cli.health {
+        report_health(&results, &incorrect, &deliveries, &settings, cli)
+    } else {
+        HashMap::new()
+    };
+    if !incorrect.is_empty() && !cli.allow_incorrect {
+        log("Servers answering incorrectly are not applied (--allow-incorrect to allow them)");
+        drop_incorrect(&mut candidates, &incorrect);
+    }
+    if cli.apply_with == ApplyWith::Networksetup {
+        // A NextDNS profile lives in its hostname, which plain DNS settings
+        // cannot carry
+        candidates.retain(|provider| {
+            let nextdns = providers::is_nextdns_profile(&provider.ip);
+            if nextdns {
+                log(&format!(
+                    "{} can only be applied with --apply-with profile, skipping it",
+                    provider.name
+                ));
+                trace::filtered(&provider.ip, "NextDNS profile needs --apply-with profile");
+            }
+            !nextdns
+        });
+    } else {
+        drop_without_dot(&mut candidates, &settings);
+    }
+    let pops = match &cli.region {
+        Some(region) => prefer_region(&mut candidates, &results, region, settings.timeout),
+        None => HashMap::new(),
+    };
 
     // 5. Set to fastest
-    let (fastest_name, fastest_ip, fastest_latency) = latencies[0];
+    let (fastest, fastest_latency) = candidates
+        .first()
+        .and_then(|provider| {
+            let (_, measurement) = results.iter().find(|(other, _)| other.ip == provider.ip)?;
+            Some((*provider, measurement.latency()?))
+        })
+        .ok_or(Error::NoResponse)?;
+    trace::winner(fastest, std::slice::from_ref(&fastest.ip));
+    let keep = cli.keep_if_best && original_dns.contains(&fastest.ip);
     log(&format!(
-        "\nSetting DNS to fastest server: {} ({}) with latency {:?}",
-        fastest_name, fastest_ip, fastest_latency
+        "\n{}: {} ({}) with latency {}",
+        if apply && !keep {
+            "Setting DNS to best server"
+        } else {
+            "Best server"
+        },
+        fastest.name,
+        fastest.ip,
+        format_duration(fastest_latency, NumberFormat::from_cli(cli))
     ));
-    set_dns(fastest_ip);
+    if cli.describe {
+        log(&format!("  {}", fastest.description));
+    }
+    if cli.explain {
+        explain_choice(&results, fastest, &original_dns, &pops, cli);
+        if let Some(health) = grades.get(&fastest.ip) {
+            log(&format!("  health {}", health));
+        }
+    }
+    if keep {
+        if let Some(rollback) = rollback {
+            rollback.disarm();
+        }
+        log("\nCurrent DNS is already optimal, leaving it untouched");
+        trace::outcome("current DNS already optimal, left untouched");
+        return write_html_report(&results, Some(&fastest.ip), cli);
+    }
+    if !apply {
+        log("Not applying it outside the --window, deferred until the window opens");
+        trace::outcome("outside the --window, not applied");
+        return write_html_report(&results, Some(&fastest.ip), cli);
+    }
+    let mut servers = vec![fastest.ip.clone()];
+    if cli.servers > 1 {
+        match pick_secondary(&candidates, fastest, !cli.no_diverse) {
+            Some(secondary) => {
+                log(&format!(
+                    "Secondary server: {} ({})",
+                    secondary.name, secondary.ip
+                ));
+                servers.push(secondary.ip.clone());
+            }
+            None => warn("no other server answered, applying the fastest one only"),
+        }
+    }
+    if cli.prepend {
+        let kept: Vec<&String> = original_dns
+            .iter()
+            .filter(|ip| {
+                !servers.contains(ip) && !providers.iter().any(|provider| &provider.ip == *ip)
+            })
+            .collect();
+        if !kept.is_empty() {
+            log(&format!(
+                "Keeping existing servers after it: {}",
+                kept.iter()
+                    .map(|ip| ip.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        servers.extend(kept.into_iter().cloned());
+    }
+    trace::winner(fastest, &servers);
+    if cli.print_commands {
+        if let Some(rollback) = rollback {
+            rollback.disarm();
+        }
+        log("\nCommands that would apply it:");
+        trace::outcome("only printed the commands, nothing applied");
+        let reset = !cli.prepend && !cli.keep_if_best && !manual_dns.is_empty();
+        for command in system::commands(&planned_changes(cli, &servers, &manual_dns), reset) {
+            println!("{}", command);
+        }
+        return write_html_report(&results, Some(&fastest.ip), cli);
+    }
+    if !confirm_apply(cli, fastest) {
+        if let Some(rollback) = rollback {
+            rollback.disarm();
+        }
+        log("\nNot applied, keeping the original DNS configuration");
+        trace::outcome("not confirmed, original DNS kept");
+        if !cli.prepend && cli.apply_with == ApplyWith::Networksetup {
+            restore_dns(&manual_dns)?;
+        }
+        return write_html_report(&results, Some(&fastest.ip), cli);
+    }
+    // The confirmation may have waited through a sleep or a VPN toggle
+    if let Err(err) = ensure_same_route(&route) {
+        if let Some(rollback) = rollback {
+            rollback.disarm();
+        }
+        if !cli.prepend && cli.apply_with == ApplyWith::Networksetup {
+            restore_dns(&manual_dns)?;
+        }
+        return Err(err);
+    }
+    match cli.apply_with {
+        ApplyWith::Networksetup => {
+            system::apply(&planned_changes(cli, &servers, &manual_dns))?;
+            trace::outcome(&format!("applied {}", servers.join(", ")));
+            if cli.verify_persistent {
+                verify_persistent(&servers)?;
+            }
+        }
+        ApplyWith::Profile => {
+            profile::install(&fastest.name, &servers)?;
+            trace::outcome(&format!(
+                "profile for {} waiting to be installed",
+                servers.join(", ")
+            ));
+            for split in &cli.split_dns {
+                set_split_dns(&split.domain, &split.servers)?;
+            }
+            for domain in &cli.remove_split_dns {
+                remove_split_dns(domain)?;
+            }
+            // Nothing changes until the profile is approved
+            if let Some(rollback) = rollback {
+                rollback.disarm();
+            }
+            log(&format!(
+                "\nDNS over TLS profile for {} is waiting to be installed",
+                fastest.name
+            ));
+            return write_html_report(&results, Some(&fastest.ip), cli);
+        }
+    }
 
     // 6. Show final DNS configuration
     log("\nFinal DNS configuration:");
     print_current_dns();
+    if let Some(rollback) = rollback {
+        rollback.disarm();
+    }
+
+    // 7. Summarize the change
+    match change_summary(
+        &original_dns,
+        (fastest, fastest_latency),
+        &results,
+        &settings,
+    ) {
+        Some((summary, previous_latency)) => {
+            log(&format!("\n{}.", summary));
+            if let Some(estimate) = previous_latency.and_then(|previous| {
+                savings_estimate(previous, fastest_latency, cli.queries_per_day)
+            }) {
+                log(&format!("{} (--queries-per-day to adjust)", estimate));
+            }
+            if cli.notify {
+                notify(&format!("{}.", summary));
+            }
+        }
+        None => log("\nFastest server was already in use, nothing changed"),
+    }
 
+    write_html_report(&results, Some(&fastest.ip), cli)?;
     log("\nDNS optimization completed!");
+    Ok(())
+}
+
+/// Checks that the applied servers were saved, not just made active, see
+/// [`get_persistent_dns`].
+fn verify_persistent(servers: &[String]) -> Result<(), Error> {
+    let saved = get_persistent_dns()?;
+    if saved != servers {
+        warn("the new DNS is active but not saved, it will be lost on reboot");
+        return Err(Error::Verification {
+            expected: servers.to_vec(),
+            actual: saved,
+        });
+    }
+    log("DNS is saved in the persistent configuration and will survive a reboot");
+    Ok(())
+}
+
+/// Servers `ensure --provider` applies for `target`: an IP address as is, a
+/// server by name, or every server of a provider family in list order.
+fn ensure_servers(target: &str, providers: &[Provider]) -> Result<Vec<String>, Error> {
+    if target.parse::<IpAddr>().is_ok() {
+        return Ok(vec![target.to_string()]);
+    }
+    if let Some(provider) = providers
+        .iter()
+        .find(|provider| provider.name.eq_ignore_ascii_case(target))
+    {
+        return Ok(vec![provider.ip.clone()]);
+    }
+    let family: Vec<String> = providers
+        .iter()
+        .filter(|provider| provider.family.eq_ignore_ascii_case(target))
+        .map(|provider| provider.ip.clone())
+        .collect();
+    if family.is_empty() {
+        Err(Error::UnknownProvider(target.to_string()))
+    } else {
+        Ok(family)
+    }
+}
+
+/// Applies a provider only if the current DNS differs, for idempotent
+/// provisioning.
+///
+/// No latency is measured. Prints "unchanged" when the current servers
+/// already are the provider's, otherwise applies them and prints "changed";
+/// both exit with status 0.
+fn ensure(target: &str, cli: &Cli) -> Result<(), Error> {
+    let servers = ensure_servers(target, &load_providers(cli))?;
+    let current = get_current_dns();
+    if current == servers {
+        log(&format!("DNS already set to {}", servers.join(", ")));
+        println!("unchanged");
+        return Ok(());
+    }
+    log(&format!(
+        "DNS is {}, applying {}",
+        if current.is_empty() {
+            "not set".to_string()
+        } else {
+            current.join(", ")
+        },
+        target
+    ));
+    set_dns(&servers)?;
+    println!("changed");
+    Ok(())
+}
+
+/// Runs [`optimize`] every `minutes` until the process is stopped.
+///
+/// A failed cycle is logged and the next one runs as scheduled. With
+/// `--window`, cycles outside the window only measure and log the server they
+/// would apply, and the next cycle is brought forward to when the window
+/// opens so the deferred change happens then.
+///
+/// Being stopped with a signal ends the loop cleanly: a wait ends at once, a
+/// cycle still measuring is abandoned with the original DNS restored, and one
+/// already applying finishes first, so the configuration and the history
+/// log are never left half written.
+fn watch(minutes: u64, cli: &Cli, rng: &mut Rng) -> Result<(), Error> {
+    let handler = ctrlc::set_handler(|| {
+        if !SHUTDOWN.swap(true, Ordering::SeqCst) {
+            log("\nStopping after the current step...");
+        }
+    });
+    if let Err(err) = handler {
+        warn(&format!(
+            "cannot handle stop signals, stopping may interrupt a change: {}",
+            err
+        ));
+    }
+    let interval = Duration::from_secs(minutes * 60);
+    let mut smoothing = cli.smoothing.map(Ewma::new);
+    let mut cache = cli.cache_stats.then(CacheStats::default);
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        let apply = cli
+            .window
+            .as_ref()
+            .is_none_or(|window| window.contains(chrono::Local::now().time()));
+        if let Err(err) = optimize(cli, apply, smoothing.as_mut(), cache.as_mut(), rng) {
+            error(&err.to_string());
+        }
+        if SHUTDOWN.load(Ordering::SeqCst) {
+            break;
+        }
+        let pause = match &cli.window {
+            Some(window) if !apply => interval.min(window.until_open(chrono::Local::now().time())),
+            _ => interval,
+        };
+        log(&format!(
+            "\nNext check in {} minute(s){}",
+            pause.as_secs().div_ceil(60),
+            match &cli.window {
+                Some(window) => format!(", changes allowed {}", window),
+                None => String::new(),
+            }
+        ));
+        let wake = Instant::now() + pause;
+        while !SHUTDOWN.load(Ordering::SeqCst) && Instant::now() < wake {
+            thread::sleep(SHUTDOWN_POLL.min(wake - Instant::now()));
+        }
+    }
+    log("Stopped watching");
+    Ok(())
+}
+
+/// Pause after DNS changes: `--settle`, or a default that is shorter when no
+/// one is watching the run.
+fn settle_delay(cli: &Cli) -> Duration {
+    match cli.settle {
+        Some(ms) => Duration::from_millis(ms),
+        None if system::is_interactive() => system::SETTLE_DELAY_INTERACTIVE,
+        None => system::SETTLE_DELAY_SCRIPTED,
+    }
+}
+
+/// Runs the requested mode and exits with a non-zero status on failure, see
+/// [`Error::exit_code`].
+///
+/// * Without a subcommand, optimizes the system DNS, see [`optimize`]; with
+///   `--watch`, again and again, see [`watch`]
+/// * With `--scope`, only the resolvers for that scope are benchmarked
+/// * With `--compare`, the given servers are measured head to head, see [`compare`]
+/// * With `--eval`, only a shell assignment of the winner is printed, see
+///   [`eval_winner`]
+/// * With `--current-only`, only the configured servers are measured, see
+///   [`current_only`]
+/// * The `bench` subcommand only measures, see [`bench`]
+/// * The `doctor` subcommand checks the environment, see [`doctor::diagnose`]
+/// * The `ensure` subcommand applies a given provider if needed, see [`ensure`]
+/// * The `compare` subcommand compares two tagged runs, see [`compare_tags`]
+fn main() {
+    let mut cli = Cli::parse();
+    // Every random choice of the run (test order, interleave passes, random
+    // probe names) comes from this seed, logged so a run can be repeated
+    let seed = *cli.seed.get_or_insert_with(|| fastrand::u64(..));
+    let mut rng = Rng::with_seed(seed);
+    logging::init(cli.log_format);
+    system::init(settle_delay(&cli), cli.verify_retries);
+    source::init(cli.source, cli.vrf.clone());
+    log("=== DNS Optimization Tool ===");
+    // Held until the process exits
+    let _lock = match cli.once {
+        Some(once) => match lock::acquire(once == Once::Wait) {
+            Ok(lock) => Some(lock),
+            Err(err) => {
+                error(&err.to_string());
+                process::exit(err.exit_code());
+            }
+        },
+        None => None,
+    };
+
+    if cli.trace_json.is_some() {
+        trace::init();
+    }
+
+    let mut result = match &cli.command {
+        Some(CliCommand::Bench) => bench(&cli, &mut rng),
+        Some(CliCommand::Doctor) => doctor::diagnose(),
+        Some(CliCommand::Ensure { provider }) => ensure(provider, &cli),
+        Some(CliCommand::Compare { before, after }) => compare_tags(before, after),
+        Some(CliCommand::Providers) => list_providers(&cli, &mut rng),
+        None if cli.eval => eval_winner(&cli, &mut rng),
+        None if !cli.compare.is_empty() => compare(&cli.compare, &cli, &mut rng),
+        None if cli.current_only => current_only(&cli, &mut rng),
+        None => match &cli.scope {
+            Some(scope) => benchmark_scope(scope, &cli, &mut rng),
+            None => match cli.watch {
+                Some(minutes) => watch(minutes, &cli, &mut rng),
+                None => optimize(&cli, true, None, None, &mut rng),
+            },
+        },
+    };
+    if let Some(path) = &cli.trace_json {
+        match trace::finish(path, result.as_ref().err()) {
+            Ok(()) => log(&format!("Wrote decision trace to {}", path.display())),
+            Err(err) if result.is_err() => error(&err.to_string()),
+            Err(err) => result = Err(err),
+        }
+    }
+    if let Err(err) = result {
+        error(&err.to_string());
+        process::exit(err.exit_code());
+    }
 }
@@ -0,0 +1,83 @@
+//! Latency samples collected while probing a DNS server.
+
+use std::time::Duration;
+
+/// Outcome of probing a single DNS server a number of times.
+#[derive(Debug, Clone, Default)]
+pub struct Measurement {
+    /// Round-trip times of the probes that got a response
+    pub samples: Vec<Duration>,
+    /// Number of probes sent
+    pub sent: usize,
+    /// Round-trip time of a first query that included connection setup, for
+    /// transports where that setup dominates (DoH)
+    pub cold: Option<Duration>,
+    /// Mean time to set up a connection (TCP connect, plus the TLS handshake
+    /// for DoT and DoH), which the samples leave out; `None` for UDP and ICMP
+    pub setup: Option<Duration>,
+    /// TTL of the first ICMP reply, a hint at how many hops away the server is
+    pub ttl: Option<u8>,
+    /// Whether the server minimizes query names, `None` unless checked with
+    /// `--check-qname-minimization`
+    pub minimizes: Option<bool>,
+}
+
+impl Measurement {
+    /// Mean round-trip time of the answered probes, `None` if nothing answered.
+    pub fn latency(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    /// Round-trip time that `percentile`% of the answered probes were at or
+    /// under, `None` if nothing answered.
+    pub fn percentile(&self, percentile: u8) -> Option<Duration> {
+        crate::stats::percentile(&self.samples, percentile)
+    }
+
+    /// Sample standard deviation of the round-trip times, `None` with fewer
+    /// than two answered probes.
+    pub fn std_dev(&self) -> Option<Duration> {
+        crate::stats::variance_ms(&self.samples)
+            .map(|variance| Duration::from_secs_f64(variance.sqrt() / 1000.0))
+    }
+
+    /// Mean difference between consecutive round-trip times, `None` with fewer
+    /// than two answered probes.
+    pub fn jitter(&self) -> Option<Duration> {
+        if self.samples.len() < 2 {
+            return None;
+        }
+        let total: Duration = self
+            .samples
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum();
+        Some(total / (self.samples.len() - 1) as u32)
+    }
+
+    /// Adds the probes of a later measurement of the same server.
+    pub fn merge(&mut self, other: Measurement) {
+        self.samples.extend(other.samples);
+        self.sent += other.sent;
+        self.cold = self.cold.or(other.cold);
+        self.setup = self.setup.or(other.setup);
+        self.ttl = self.ttl.or(other.ttl);
+        self.minimizes = self.minimizes.or(other.minimizes);
+    }
+
+    /// Fraction of probes that got no response, between 0.0 and 1.0.
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 1.0;
+        }
+        1.0 - self.samples.len() as f64 / self.sent as f64
+    }
+
+    /// Whether at least one probe got a response.
+    pub fn reachable(&self) -> bool {
+        !self.samples.is_empty()
+    }
+}
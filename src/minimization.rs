@@ -0,0 +1,56 @@
+//! Detection of resolvers that practice QNAME minimization (RFC 9156):
+//! asking each authoritative server only for the part of a name it is
+//! responsible for, instead of sending every full query name to the root
+//! and TLD servers on the way.
+
+use crate::dns;
+use std::{fmt, time::Duration};
+
+/// Test zone whose `TXT` answer tells whether the resolver that fetched it
+/// minimized the name, as published by internet.nl.
+pub const TEST_NAME: &str = "qnamemintest.internet.nl";
+
+/// Whether a resolver minimizes the names it sends upstream.
+pub enum Minimization {
+    /// Only the needed labels reach each authoritative server
+    Minimizes,
+    /// Full query names are sent to every server on the way
+    Leaks,
+    /// The check could not be performed, with the reason
+    Unknown(String),
+}
+
+impl fmt::Display for Minimization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Minimization::Minimizes => write!(f, "minimizes query names"),
+            Minimization::Leaks => write!(f, "leaks full query names upstream"),
+            Minimization::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// Looks up the `TXT` record of [`TEST_NAME`] on the resolver at `address`.
+/// The test zone's servers answer `HOORAY - QNAME minimisation is enabled`
+/// when the query reached them minimized, `NO - ...` otherwise.
+pub fn check(address: &str, timeout: Duration) -> Minimization {
+    let server = match dns::resolve_server(address) {
+        Ok(server) => server,
+        Err(err) => return Minimization::Unknown(err.to_string()),
+    };
+    let response = match dns::lookup_txt(server, TEST_NAME, timeout) {
+        Ok(response) => response,
+        Err(err) => return Minimization::Unknown(err.to_string()),
+    };
+    if response.rcode != 0 {
+        return Minimization::Unknown(format!("response code {}", response.rcode));
+    }
+    let verdict = |prefix: &str| response.texts.iter().any(|text| text.starts_with(prefix));
+    if verdict("HOORAY") {
+        Minimization::Minimizes
+    } else if verdict("NO") {
+        Minimization::Leaks
+    } else {
+        Minimization::Unknown(format!("no verdict in the {} answer", TEST_NAME))
+    }
+}
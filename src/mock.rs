@@ -0,0 +1,67 @@
+//! Predetermined latencies for `--mock-latencies`, which replace the probes
+//! so the ranking, selection and apply steps can be exercised without the
+//! network giving different numbers every run.
+
+use crate::measurement::Measurement;
+use std::{collections::HashMap, fs, time::Duration};
+
+/// Mocked measurement of every server listed in the file, by address.
+#[derive(Clone, Default)]
+pub struct MockLatencies(HashMap<String, Measurement>);
+
+impl MockLatencies {
+    /// The measurement of `address`; servers missing from the file never
+    /// answer.
+    pub fn measurement(&self, address: &str) -> Measurement {
+        self.0.get(address).cloned().unwrap_or(Measurement {
+            sent: 1,
+            ..Measurement::default()
+        })
+    }
+}
+
+/// Reads a mock file: one `<address> <ms> [<ms> ...]` line per server, where
+/// every number is the round-trip time of one probe and `-` is a lost probe,
+/// e.g. `1.1.1.1 11.2 - 12.0`. Blank lines and `#` comments are ignored.
+pub fn load(path: &str) -> Result<MockLatencies, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))?;
+    parse(&text)
+}
+
+/// Parses the contents of a mock file, see [`load`].
+pub fn parse(text: &str) -> Result<MockLatencies, String> {
+    let mut servers = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut fields = line.split_whitespace();
+        let Some(address) = fields.next() else {
+            continue;
+        };
+        let mut measurement = Measurement::default();
+        for field in fields {
+            measurement.sent += 1;
+            if field == "-" {
+                continue;
+            }
+            let ms = field
+                .parse::<f64>()
+                .ok()
+                .filter(|ms| ms.is_finite() && *ms >= 0.0)
+                .ok_or_else(|| {
+                    format!(
+                        "line {}: {:?} is neither milliseconds nor -",
+                        index + 1,
+                        field
+                    )
+                })?;
+            measurement
+                .samples
+                .push(Duration::from_secs_f64(ms / 1000.0));
+        }
+        if measurement.sent == 0 {
+            return Err(format!("line {}: no latencies for {}", index + 1, address));
+        }
+        servers.insert(address.to_string(), measurement);
+    }
+    Ok(MockLatencies(servers))
+}
@@ -0,0 +1,36 @@
+//! Where wisp keeps files between runs, following the platform conventions
+//! of the `directories` crate: settings in the config directory, state such
+//! as the history in the data directory, and copies that can be fetched
+//! again in the cache directory. On macOS these are
+//! `~/Library/Application Support/wisp` and `~/Library/Caches/wisp`.
+
+use directories::ProjectDirs;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A file in the config directory, creating the directory if needed.
+pub fn config_file(name: &str) -> io::Result<PathBuf> {
+    file(name, ProjectDirs::config_dir)
+}
+
+/// A file in the data directory, creating the directory if needed.
+pub fn data_file(name: &str) -> io::Result<PathBuf> {
+    file(name, ProjectDirs::data_dir)
+}
+
+/// A file in the cache directory, creating the directory if needed.
+pub fn cache_file(name: &str) -> io::Result<PathBuf> {
+    file(name, ProjectDirs::cache_dir)
+}
+
+/// Joins `name` to the directory `dir` picks, failing when the platform
+/// gives no home directory to put it in.
+fn file(name: &str, dir: fn(&ProjectDirs) -> &Path) -> io::Result<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "wisp")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    let dir = dir(&dirs);
+    fs::create_dir_all(dir)?;
+    Ok(dir.join(name))
+}
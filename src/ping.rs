@@ -0,0 +1,73 @@
+//! Parsing of `ping` output into latency samples.
+
+use crate::measurement::Measurement;
+use regex::Regex;
+use std::time::Duration;
+
+/// Parses the output of `ping -c <count>` into a [`Measurement`].
+///
+/// Every reply line (`... time=11.2 ms`) contributes one sample, so packets
+/// that timed out (`Request timeout for icmp_seq 1`) only count toward loss.
+/// Duplicate replies are ignored, and the first reply's TTL is kept. The
+/// number of packets sent is taken from the summary line, falling back to
+/// `count` when ping exited before printing one (e.g. an unknown host or
+/// truncated output).
+pub fn parse_ping_output(output: &str, count: usize) -> Measurement {
+    let reply_pattern = Regex::new(r"time[=<]\s*([\d.]+)\s*ms").unwrap();
+    let sent_pattern = Regex::new(r"(\d+) packets transmitted").unwrap();
+    let ttl_pattern = Regex::new(r"ttl=(\d+)").unwrap();
+
+    let samples: Vec<Duration> = output
+        .lines()
+        .filter(|line| !line.contains("DUP!"))
+        .filter_map(|line| reply_pattern.captures(line))
+        .filter_map(|cap| cap[1].parse::<f64>().ok())
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .collect();
+    let ttl = ttl_pattern
+        .captures(output)
+        .and_then(|cap| cap[1].parse().ok());
+    let timeouts = output
+        .lines()
+        .filter(|line| line.starts_with("Request timeout"))
+        .count();
+    let sent = sent_pattern
+        .captures(output)
+        .and_then(|cap| cap[1].parse().ok())
+        .unwrap_or(count)
+        .max(samples.len() + timeouts);
+
+    Measurement {
+        samples,
+        sent,
+        cold: None,
+        setup: None,
+        ttl,
+        minimizes: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_replies_and_timeouts() {
+        let output = "\
+PING 9.9.9.9 (9.9.9.9): 56 data bytes
+64 bytes from 9.9.9.9: icmp_seq=0 ttl=57 time=10.000 ms
+Request timeout for icmp_seq 1
+64 bytes from 9.9.9.9: icmp_seq=2 ttl=57 time=20.000 ms
+
+--- 9.9.9.9 ping statistics ---
+3 packets transmitted, 2 packets received, 33.3% packet loss
+round-trip min/avg/max/stddev = 10.000/15.000/20.000/5.000 ms
+";
+        let measurement = parse_ping_output(output, 3);
+        assert_eq!(measurement.samples.len(), 2);
+        assert_eq!(measurement.sent, 3);
+        assert_eq!(measurement.latency(), Some(Duration::from_millis(15)));
+        assert!((measurement.loss() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(measurement.ttl, Some(57));
+    }
+}
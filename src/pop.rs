@@ -0,0 +1,27 @@
+//! Identifying which point of presence (POP) of an anycast resolver answers,
+//! for the `--region` preference.
+
+use crate::dns;
+use std::time::Duration;
+
+/// How much slower than the best server an in-region one may be and still
+/// be preferred.
+pub const REGION_MARGIN: Duration = Duration::from_millis(5);
+
+/// Returns the instance name the resolver at `address` reports for itself,
+/// such as `FRA` (Cloudflare) or `res100.fra.rrdns.pch.net` (Quad9). Not
+/// every provider answers.
+pub fn identify(address: &str, timeout: Duration) -> Option<String> {
+    let server = dns::resolve_server(address).ok()?;
+    dns::server_identity(server, timeout)
+        .ok()
+        .flatten()
+        .filter(|identity| !identity.is_empty())
+}
+
+/// Whether a POP name mentions `region`, an airport or city code like `fra`,
+/// as one of its dot or dash separated parts.
+pub fn in_region(pop: &str, region: &str) -> bool {
+    pop.split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|part| part.eq_ignore_ascii_case(region))
+}
@@ -0,0 +1,130 @@
+//! Applying DNS through a configuration profile (`--apply-with profile`)
+//! instead of `networksetup`, for managed Macs whose per-service settings get
+//! reverted by policy. The profile carries a `com.apple.dnsSettings.managed`
+//! payload, which macOS only accepts for encrypted DNS, so the servers are
+//! configured as DNS over TLS.
+
+use crate::{error::Error, logging::log, paths, system};
+use std::{fs, net::IpAddr};
+
+/// Name of the profile in the data directory, see [`paths::data_file`].
+const PROFILE_FILE: &str = "wisp-dns.mobileconfig";
+
+/// Identifier of the profile; keeping it the same across runs makes a new
+/// profile replace the previous one instead of stacking up.
+const PROFILE_IDENTIFIER: &str = "wisp.dns";
+
+/// Writes the profile for `servers` and hands it to macOS, which asks for
+/// approval in System Settings; the same file can be deployed through MDM.
+pub fn install(name: &str, servers: &[String]) -> Result<(), Error> {
+    let path = paths::data_file(PROFILE_FILE).map_err(|err| Error::Write {
+        path: PROFILE_FILE.to_string(),
+        message: err.to_string(),
+    })?;
+    fs::write(&path, mobileconfig(name, servers)).map_err(|err| Error::Write {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    })?;
+    log(&format!("Wrote DNS profile to {}", path.display()));
+    system::run("open", &[&path.display().to_string()])?;
+    log("Approve it in System Settings > Privacy & Security > Profiles, or deploy it through your MDM");
+    Ok(())
+}
+
+/// The profile for `servers` as a property list. A server given by hostname
+/// (a NextDNS profile, say) becomes the TLS server name, which macOS
+/// resolves itself; only one fits in a profile, so further ones are left out.
+pub fn mobileconfig(name: &str, servers: &[String]) -> String {
+    let (ips, hostnames): (Vec<&String>, Vec<&String>) = servers.iter().partition(|server| {
+        server
+            .split('%')
+            .next()
+            .unwrap_or(server)
+            .parse::<IpAddr>()
+            .is_ok()
+    });
+    let mut settings = String::new();
+    if !ips.is_empty() {
+        let addresses: String = ips
+            .iter()
+            .map(|server| format!("\n\t\t\t\t\t<string>{}</string>", escape(server)))
+            .collect();
+        settings.push_str(&format!(
+            "\n\t\t\t\t<key>ServerAddresses</key>\n\t\t\t\t<array>{}\n\t\t\t\t</array>",
+            addresses
+        ));
+    }
+    if let Some(hostname) = hostnames.first() {
+        settings.push_str(&format!(
+            "\n\t\t\t\t<key>ServerName</key>\n\t\t\t\t<string>{}</string>",
+            escape(hostname)
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>PayloadContent</key>
+	<array>
+		<dict>
+			<key>DNSSettings</key>
+			<dict>
+				<key>DNSProtocol</key>
+				<string>TLS</string>{settings}
+			</dict>
+			<key>PayloadDisplayName</key>
+			<string>DNS settings</string>
+			<key>PayloadIdentifier</key>
+			<string>{identifier}.settings</string>
+			<key>PayloadType</key>
+			<string>com.apple.dnsSettings.managed</string>
+			<key>PayloadUUID</key>
+			<string>{settings_uuid}</string>
+			<key>PayloadVersion</key>
+			<integer>1</integer>
+		</dict>
+	</array>
+	<key>PayloadDisplayName</key>
+	<string>wisp: {name}</string>
+	<key>PayloadIdentifier</key>
+	<string>{identifier}</string>
+	<key>PayloadType</key>
+	<string>Configuration</string>
+	<key>PayloadUUID</key>
+	<string>{profile_uuid}</string>
+	<key>PayloadVersion</key>
+	<integer>1</integer>
+</dict>
+</plist>
+"#,
+        identifier = PROFILE_IDENTIFIER,
+        name = escape(name),
+        settings_uuid = uuid(),
+        profile_uuid = uuid(),
+    )
+}
+
+/// A random (version 4) UUID, as every payload needs its own.
+fn uuid() -> String {
+    // Version nibble 4 and variant bits 10 on top of random bits
+    let bits = fastrand::u128(..) & !(0xf_u128 << 76) & !(0xc_u128 << 60)
+        | (0x4_u128 << 76)
+        | (0x8_u128 << 60);
+    let hex = format!("{:032X}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Escapes text for an XML element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,363 @@
+//! Built-in list of public DNS providers tested by wisp.
+
+use crate::cli::Method;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// A public DNS resolver endpoint that can be benchmarked and applied.
+pub struct Provider {
+    /// Human readable label, e.g. "Cloudflare Primary"
+    pub name: String,
+    /// Organization running the resolver, e.g. "Cloudflare"
+    pub family: String,
+    /// IP address of the resolver
+    pub ip: String,
+    /// Short note on what the provider is known for
+    pub description: String,
+    /// Measured for reference only and never applied
+    pub baseline: bool,
+    /// Port the resolver listens on, `None` for the protocol's standard one
+    pub port: Option<u16>,
+    /// The only method the resolver is probed with, `None` for `--method`
+    pub protocol: Option<Method>,
+}
+
+impl Provider {
+    /// Creates a standalone provider that forms its own family.
+    pub fn new(name: &str, ip: &str, description: &str) -> Self {
+        Provider {
+            name: name.to_string(),
+            family: name.to_string(),
+            ip: ip.to_string(),
+            description: description.to_string(),
+            baseline: false,
+            port: None,
+            protocol: None,
+        }
+    }
+}
+
+/// Returns the loopback resolver, measured as a baseline for the public
+/// providers when a local caching resolver is running.
+pub fn local_resolver() -> Provider {
+    Provider {
+        baseline: true,
+        ..Provider::new("Local", "127.0.0.1", "Local caching resolver")
+    }
+}
+
+/// Returns a DNS server handed out by DHCP, measured as a baseline for
+/// leaving DNS on automatic.
+pub fn automatic_resolver(ip: &str) -> Provider {
+    Provider {
+        family: "Automatic".to_string(),
+        baseline: true,
+        ..Provider::new("Automatic (DHCP)", ip, "Handed out by the network")
+    }
+}
+
+/// Address standing in for the operating system's resolver, which has none
+/// of its own, see [`system_resolver`].
+pub const SYSTEM_ADDRESS: &str = "system";
+
+/// Returns the operating system's resolver, measured through `getaddrinfo`
+/// as a baseline for what applications actually wait for.
+pub fn system_resolver() -> Provider {
+    Provider {
+        family: "System".to_string(),
+        baseline: true,
+        ..Provider::new(
+            "System",
+            SYSTEM_ADDRESS,
+            "The OS resolver through getaddrinfo, caching included",
+        )
+    }
+}
+
+/// Checks whether an address is the [`system_resolver`] rather than a
+/// server that can be queried directly.
+pub fn is_system(address: &str) -> bool {
+    address == SYSTEM_ADDRESS
+}
+
+/// Returns the NextDNS endpoint of one profile, e.g. `abc123`. The profile is
+/// picked by the hostname, which DoT and DoH send as the TLS server name;
+/// plain DNS and ping reach the same anycast servers without it.
+pub fn nextdns(profile: &str) -> Provider {
+    Provider {
+        family: "NextDNS".to_string(),
+        ..Provider::new(
+            &format!("NextDNS ({})", profile),
+            &format!("{}.dns.nextdns.io", profile),
+            "Your NextDNS profile, with its own filtering and logs",
+        )
+    }
+}
+
+/// Checks whether an address is the endpoint of a NextDNS profile, see
+/// [`nextdns`].
+pub fn is_nextdns_profile(address: &str) -> bool {
+    address
+        .strip_suffix(".dns.nextdns.io")
+        .is_some_and(is_valid_nextdns_profile)
+}
+
+/// Checks the shape of a NextDNS profile ID: six hexadecimal digits.
+pub fn is_valid_nextdns_profile(profile: &str) -> bool {
+    profile.len() == 6 && profile.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Builds the endpoints of one provider organization, primary first.
+fn family(family: &str, description: &str, servers: &[(&str, &str)]) -> Vec<Provider> {
+    servers
+        .iter()
+        .map(|&(name, ip)| Provider {
+            family: family.to_string(),
+            ..Provider::new(name, ip, description)
+        })
+        .collect()
+}
+
+/// Returns the popular public DNS providers, primary and secondary servers.
+pub fn builtin_providers() -> Vec<Provider> {
+    [
+        family(
+            "Cloudflare",
+            "Known for speed and privacy",
+            &[
+                ("Cloudflare Primary", "1.1.1.1"),
+                ("Cloudflare Secondary", "1.0.0.1"),
+            ],
+        ),
+        family(
+            "Google",
+            "Most popular, highly reliable",
+            &[
+                ("Google Primary", "8.8.8.8"),
+                ("Google Secondary", "8.8.4.4"),
+            ],
+        ),
+        family(
+            "Quad9",
+            "Security focused, blocks malicious domains",
+            &[
+                ("Quad9 Primary", "9.9.9.9"),
+                ("Quad9 Secondary", "149.112.112.112"),
+            ],
+        ),
+        family(
+            "OpenDNS",
+            "Cisco owned, extensive filtering",
+            &[
+                ("OpenDNS Primary", "208.67.222.222"),
+                ("OpenDNS Secondary", "208.67.220.220"),
+            ],
+        ),
+        family(
+            "AdGuard",
+            "Ad blocking, no logging",
+            &[
+                ("AdGuard Primary", "94.140.14.14"),
+                ("AdGuard Secondary", "94.140.15.15"),
+            ],
+        ),
+        family(
+            "CleanBrowsing",
+            "Family friendly filtering",
+            &[
+                ("CleanBrowsing Primary", "185.228.168.9"),
+                ("CleanBrowsing Secondary", "185.228.169.9"),
+            ],
+        ),
+        // Level3/CenturyLink
+        family(
+            "Level3",
+            "Enterprise grade",
+            &[
+                ("Level3 Primary", "4.2.2.1"),
+                ("Level3 Secondary", "4.2.2.2"),
+            ],
+        ),
+        // Comodo Secure
+        family(
+            "Comodo",
+            "Security focused",
+            &[
+                ("Comodo Primary", "8.26.56.26"),
+                ("Comodo Secondary", "8.20.247.20"),
+            ],
+        ),
+        family(
+            "Verisign",
+            "Enterprise reliability",
+            &[
+                ("Verisign Primary", "64.6.64.6"),
+                ("Verisign Secondary", "64.6.65.6"),
+            ],
+        ),
+        family(
+            "NextDNS",
+            "Cloud-based, customizable",
+            &[("NextDNS", "45.90.28.167")],
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Keeps only the first (primary) endpoint of every provider family.
+pub fn first_per_family(providers: Vec<Provider>) -> Vec<Provider> {
+    let mut kept: Vec<Provider> = Vec::new();
+    for provider in providers {
+        if !kept.iter().any(|other| other.family == provider.family) {
+            kept.push(provider);
+        }
+    }
+    kept
+}
+
+/// Parses a provider list with one `<address> [name]` entry per line.
+///
+/// Blank lines and lines starting with `#` are ignored. Entries without a
+/// name are labeled with their address.
+pub fn parse_provider_list(text: &str) -> Vec<Provider> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (ip, name) = line.split_once(char::is_whitespace).unwrap_or((line, line));
+            Provider::new(name.trim(), ip, "Custom provider")
+        })
+        .collect()
+}
+
+/// One server of a JSON or TOML provider list.
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(alias = "ip")]
+    address: String,
+    name: Option<String>,
+    family: Option<String>,
+    description: Option<String>,
+    port: Option<u16>,
+    protocol: Option<String>,
+}
+
+/// Shapes a structured provider list can take.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Document {
+    List(Vec<Entry>),
+    Table { providers: Vec<Entry> },
+}
+
+/// Parses a structured provider list: a JSON array of entries, or a JSON
+/// object or TOML document with a `providers` array.
+///
+/// Every entry needs an `address` (or `ip`); `name`, `family`,
+/// `description`, `port` and `protocol` (a `--method` value) are optional.
+/// Addresses are validated later, like those of line-based lists.
+pub fn parse_provider_document(text: &str) -> Result<Vec<Provider>, String> {
+    let document = serde_json::from_str::<Document>(text)
+        .or_else(|_| toml::from_str::<Document>(text))
+        .map_err(|err| format!("not a JSON or TOML provider list: {}", err.message()))?;
+    let entries = match document {
+        Document::List(entries) | Document::Table { providers: entries } => entries,
+    };
+    entries
+        .into_iter()
+        .map(|entry| {
+            let protocol = entry
+                .protocol
+                .map(|protocol| {
+                    Method::from_str(&protocol, true).map_err(|_| {
+                        format!("unknown protocol {:?} for {}", protocol, entry.address)
+                    })
+                })
+                .transpose()?;
+            let name = entry.name.unwrap_or_else(|| entry.address.clone());
+            Ok(Provider {
+                family: entry.family.unwrap_or_else(|| name.clone()),
+                port: entry.port,
+                protocol,
+                ..Provider::new(
+                    &name,
+                    &entry.address,
+                    entry.description.as_deref().unwrap_or("Custom provider"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Removes providers that point at a server already in the list, keeping
+/// the first label for each. A server is an address with its port and
+/// protocol, so the same address behind another port or over DoT or DoH
+/// stays in the list.
+///
+/// Returns the kept providers and, for every dropped one, the name of the
+/// provider it duplicated.
+pub fn dedup_providers(providers: Vec<Provider>) -> (Vec<Provider>, Vec<(Provider, String)>) {
+    let mut kept: Vec<Provider> = Vec::new();
+    let mut duplicates = Vec::new();
+    for provider in providers {
+        let key = (address_key(&provider.ip), provider.port, provider.protocol);
+        match kept
+            .iter()
+            .find(|other| (address_key(&other.ip), other.port, other.protocol) == key)
+        {
+            Some(other) => {
+                let original = other.name.clone();
+                duplicates.push((provider, original));
+            }
+            None => kept.push(provider),
+        }
+    }
+    (kept, duplicates)
+}
+
+/// Normalizes an address so different spellings of the same IP compare
+/// equal, e.g. "2606:4700:4700:0:0:0:0:1111" and "2606:4700:4700::1111".
+fn address_key(address: &str) -> String {
+    match address.parse::<IpAddr>() {
+        Ok(ip) => ip.to_string(),
+        Err(_) => address.trim_end_matches('.').to_ascii_lowercase(),
+    }
+}
+
+/// Checks whether an address can be handed to the probes: an IPv4/IPv6
+/// address (IPv6 may carry a `%zone` suffix) or an RFC 1123 hostname.
+pub fn is_valid_address(address: &str) -> bool {
+    if address.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    if let Some((ip, zone)) = address.split_once('%') {
+        return !zone.is_empty() && ip.parse::<Ipv6Addr>().is_ok();
+    }
+    is_valid_hostname(address)
+}
+
+/// Checks a hostname against RFC 1123 label rules. A numeric last label is
+/// rejected so mistyped IPv4 addresses like "1.1.1.300" are not accepted.
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    let hostname = hostname.strip_suffix('.').unwrap_or(hostname);
+    if hostname.is_empty() || hostname.len() > 253 {
+        return false;
+    }
+
+    let labels: Vec<&str> = hostname.split('.').collect();
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    let numeric_tld = labels
+        .last()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()));
+
+    valid_labels && !numeric_tld
+}
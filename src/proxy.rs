@@ -0,0 +1,149 @@
+//! Tunnelling the TCP connections of the DoT and DoH probes through an HTTP
+//! (`CONNECT`) or SOCKS5 proxy, for networks that only reach the internet
+//! that way. UDP and ICMP probes cannot be proxied.
+
+use crate::source;
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Longest proxy response header accepted for a `CONNECT`.
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+/// A proxy given with `--proxy`, as `host:port`.
+#[derive(Clone)]
+pub enum Proxy {
+    /// `http://host:port`, tunnelling with `CONNECT`
+    Http(String),
+    /// `socks5://host:port`, without authentication
+    Socks5(String),
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Proxy::Http(address) => write!(f, "http://{}", address),
+            Proxy::Socks5(address) => write!(f, "socks5://{}", address),
+        }
+    }
+}
+
+/// Parses an `http://host:port` or `socks5://host:port` proxy URL.
+pub fn parse(value: &str) -> Result<Proxy, String> {
+    let (scheme, address) = value
+        .split_once("://")
+        .ok_or("expected http://HOST:PORT or socks5://HOST:PORT")?;
+    let address = address.trim_end_matches('/');
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+        _ => return Err(format!("{:?} is not a HOST:PORT address", address)),
+    }
+    match scheme.to_ascii_lowercase().as_str() {
+        "http" => Ok(Proxy::Http(address.to_string())),
+        "socks5" => Ok(Proxy::Socks5(address.to_string())),
+        _ => Err(format!("unsupported proxy scheme {:?}", scheme)),
+    }
+}
+
+/// Opens a TCP connection to `target`, through `proxy` if one is given.
+pub fn connect(
+    proxy: Option<&Proxy>,
+    target: SocketAddr,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let Some(proxy) = proxy else {
+        return source::tcp_connect(target, timeout);
+    };
+    let address = match proxy {
+        Proxy::Http(address) | Proxy::Socks5(address) => address,
+    };
+    let proxy_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "proxy address did not resolve"))?;
+    let mut stream = source::tcp_connect(proxy_address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    match proxy {
+        Proxy::Http(_) => http_connect(&mut stream, target)?,
+        Proxy::Socks5(_) => socks5_connect(&mut stream, target)?,
+    }
+    Ok(stream)
+}
+
+/// Asks an HTTP proxy to open a tunnel to `target`.
+fn http_connect(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+    write!(stream, "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target)?;
+    let mut response = Vec::new();
+    let mut byte = [0];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE {
+            return Err(proxy_error("response header too long".to_string()));
+        }
+        stream.read_exact(&mut byte)?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(proxy_error(format!("proxy answered {:?}", status))),
+    }
+}
+
+/// Asks a SOCKS5 proxy (RFC 1928) to connect to `target`.
+fn socks5_connect(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+    // Version 5, one method offered: no authentication
+    stream.write_all(&[5, 1, 0])?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice)?;
+    if choice != [5, 0] {
+        return Err(proxy_error(
+            "SOCKS proxy requires authentication".to_string(),
+        ));
+    }
+
+    let mut request = vec![5, 1, 0];
+    match target {
+        SocketAddr::V4(address) => {
+            request.push(1);
+            request.extend_from_slice(&address.ip().octets());
+        }
+        SocketAddr::V6(address) => {
+            request.push(4);
+            request.extend_from_slice(&address.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(proxy_error(format!(
+            "SOCKS proxy refused the connection (reply {})",
+            reply[1]
+        )));
+    }
+    // Skip the address the proxy bound, which wisp has no use for
+    let bound = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        kind => return Err(proxy_error(format!("unknown SOCKS address type {}", kind))),
+    };
+    let mut rest = vec![0; bound + 2];
+    stream.read_exact(&mut rest)
+}
+
+/// An error of the proxy handshake.
+fn proxy_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message)
+}
@@ -0,0 +1,84 @@
+//! Provider lists fetched from a URL, cached on disk for when the fetch
+//! fails.
+
+use crate::{
+    error::Error,
+    logging::{log, warn},
+    paths,
+    providers::{parse_provider_document, Provider},
+};
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// How long fetching a provider list may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches and parses the provider list at `url`.
+///
+/// A list that downloaded and parsed is cached; if the fetch fails or the
+/// list does not parse, the last cached copy is used instead.
+pub fn fetch_provider_list(url: &str) -> Result<Vec<Provider>, Error> {
+    log(&format!("Fetching provider list from {}", url));
+    let cache = cache_path(url);
+    let fetched = fetch(url).and_then(|text| {
+        let providers = parse_provider_document(&text)?;
+        Ok((text, providers))
+    });
+
+    match fetched {
+        Ok((text, providers)) => {
+            if let Some(cache) = &cache {
+                if let Err(err) = fs::write(cache, text) {
+                    warn(&format!(
+                        "could not cache provider list at {}: {}",
+                        cache.display(),
+                        err
+                    ));
+                }
+            }
+            Ok(providers)
+        }
+        Err(message) => {
+            let fetch_error = || Error::Fetch {
+                url: url.to_string(),
+                message: message.clone(),
+            };
+            let cache = cache.ok_or_else(fetch_error)?;
+            let text = fs::read_to_string(&cache).map_err(|_| fetch_error())?;
+            warn(&format!(
+                "failed to fetch provider list ({}), using the copy cached at {}",
+                message,
+                cache.display()
+            ));
+            parse_provider_document(&text).map_err(|message| Error::Fetch {
+                url: url.to_string(),
+                message: format!("cached copy is invalid: {}", message),
+            })
+        }
+    }
+}
+
+/// Downloads `url` as text.
+fn fetch(url: &str) -> Result<String, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .build()
+        .into();
+    agent
+        .get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| err.to_string())
+}
+
+/// Cache file for the list at `url`, `None` if there is no cache directory
+/// to keep it in.
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    paths::cache_file(&format!("providers-{:016x}", hasher.finish())).ok()
+}
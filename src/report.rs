@@ -0,0 +1,523 @@
+//! Rendering of benchmark results as a table, JSON, CSV, Markdown or an HTML
+//! page.
+
+use crate::{
+    cli::{Cli, Column, Format, Unit},
+    logging::{log, warn},
+    measurement::Measurement,
+    providers::Provider,
+};
+use serde::Serialize;
+use std::{fmt::Write as _, fs, io, path::Path, time::Duration};
+
+/// One tested server, shared by every output format.
+#[derive(Serialize)]
+pub struct ResultRow<'a> {
+    pub name: &'a str,
+    pub ip: &'a str,
+    pub description: &'a str,
+    #[serde(flatten)]
+    pub latency: Latency,
+    /// Fraction of probes that were lost, between 0.0 and 1.0
+    pub loss: f64,
+    /// TTL left in the ping replies (ICMP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    /// Whether the server minimizes query names, when checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qname_minimization: Option<bool>,
+    pub reachable: bool,
+}
+
+/// Latencies of a row in the chosen [`Unit`], which also names the fields.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Latency {
+    Ms {
+        /// Mean latency, `None` if the server never answered
+        latency_ms: Option<f64>,
+        /// Latency of the first query including connection setup (DoH only)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cold_ms: Option<f64>,
+        /// Mean connection setup time (TCP, DoT and DoH only)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        setup_ms: Option<f64>,
+    },
+    Us {
+        latency_us: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cold_us: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        setup_us: Option<f64>,
+    },
+}
+
+impl Latency {
+    /// The mean latency in the row's unit.
+    pub fn mean(&self) -> Option<f64> {
+        match self {
+            Latency::Ms { latency_ms, .. } => *latency_ms,
+            Latency::Us { latency_us, .. } => *latency_us,
+        }
+    }
+}
+
+/// How latencies are written out.
+#[derive(Clone, Copy)]
+pub struct NumberFormat {
+    pub unit: Unit,
+    /// Fractional digits, `None` for the defaults of each output
+    pub precision: Option<usize>,
+}
+
+impl NumberFormat {
+    pub fn from_cli(cli: &Cli) -> Self {
+        NumberFormat {
+            unit: cli.unit,
+            precision: cli.precision,
+        }
+    }
+}
+
+impl<'a> ResultRow<'a> {
+    pub fn new(provider: &'a Provider, measurement: &Measurement, numbers: NumberFormat) -> Self {
+        let unit = numbers.unit;
+        let latency = measurement
+            .latency()
+            .map(|latency| duration_value(latency, numbers));
+        let cold = measurement.cold.map(|cold| duration_value(cold, numbers));
+        let setup = measurement
+            .setup
+            .map(|setup| duration_value(setup, numbers));
+        ResultRow {
+            name: &provider.name,
+            ip: &provider.ip,
+            description: &provider.description,
+            latency: match unit {
+                Unit::Ms => Latency::Ms {
+                    latency_ms: latency,
+                    cold_ms: cold,
+                    setup_ms: setup,
+                },
+                Unit::Us => Latency::Us {
+                    latency_us: latency,
+                    cold_us: cold,
+                    setup_us: setup,
+                },
+            },
+            loss: measurement.loss(),
+            ttl: measurement.ttl,
+            qname_minimization: measurement.minimizes,
+            reachable: measurement.reachable(),
+        }
+    }
+}
+
+/// A duration as a number of the format's units, rounded to its precision
+/// if one was given.
+pub fn duration_value(duration: Duration, numbers: NumberFormat) -> f64 {
+    let value = match numbers.unit {
+        Unit::Ms => duration.as_secs_f64() * 1000.0,
+        Unit::Us => duration.as_secs_f64() * 1_000_000.0,
+    };
+    match numbers.precision {
+        Some(digits) => {
+            let scale = 10f64.powi(digits.min(15) as i32);
+            (value * scale).round() / scale
+        }
+        None => value,
+    }
+}
+
+/// A duration with its unit, e.g. "11.23ms" or "11230us".
+pub fn format_duration(duration: Duration, numbers: NumberFormat) -> String {
+    let value = duration_value(duration, numbers);
+    match numbers.unit {
+        Unit::Ms => format!("{:.*}ms", numbers.precision.unwrap_or(2), value),
+        Unit::Us => format!("{:.*}us", numbers.precision.unwrap_or(0), value),
+    }
+}
+
+/// Prints the sorted results in the requested format.
+///
+/// The human table only lists servers that answered, while JSON and CSV keep
+/// every tested server so monitoring can tell when a provider goes down.
+pub fn print_results(
+    results: &[(&Provider, Measurement)],
+    format: Format,
+    columns: &[Column],
+    numbers: NumberFormat,
+) {
+    let rows: Vec<ResultRow> = results
+        .iter()
+        .map(|(provider, measurement)| ResultRow::new(provider, measurement, numbers))
+        .collect();
+
+    match format {
+        Format::Human => print_table(results, columns, numbers),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&rows).expect("Failed to serialize results")
+        ),
+        Format::Csv => print_csv(&rows, numbers),
+        Format::Markdown => print_markdown(results, columns, numbers),
+    }
+}
+
+/// The columns to show, with the cold latency (DoH) added next to the warm
+/// one when measurements carry it, and the privacy column after the others
+/// when query name minimization was checked.
+fn shown_columns(results: &[(&Provider, Measurement)], columns: &[Column]) -> Vec<Column> {
+    let mut columns = columns.to_vec();
+    if !columns.contains(&Column::Privacy)
+        && results
+            .iter()
+            .any(|(_, measurement)| measurement.minimizes.is_some())
+    {
+        let end = columns
+            .iter()
+            .position(|column| *column == Column::Description)
+            .unwrap_or(columns.len());
+        columns.insert(end, Column::Privacy);
+    }
+    let has_cold = results
+        .iter()
+        .any(|(_, measurement)| measurement.cold.is_some());
+    if let (true, false, Some(index)) = (
+        has_cold,
+        columns.contains(&Column::Cold),
+        columns.iter().position(|column| *column == Column::Latency),
+    ) {
+        columns.insert(index + 1, Column::Cold);
+    }
+    columns
+}
+
+/// The header and the rows of the servers that answered, as cells.
+fn table_cells(
+    results: &[(&Provider, Measurement)],
+    columns: &[Column],
+    numbers: NumberFormat,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let header = columns
+        .iter()
+        .map(|column| column_title(*column).to_string())
+        .collect();
+    let rows = results
+        .iter()
+        .filter(|(_, measurement)| measurement.reachable())
+        .map(|(provider, measurement)| {
+            columns
+                .iter()
+                .map(|column| cell(*column, provider, measurement, numbers))
+                .collect()
+        })
+        .collect();
+    (header, rows)
+}
+
+/// Prints the human readable results table, omitting unreachable servers.
+///
+/// Every column is as wide as its longest value, so long provider names
+/// never break the alignment. Numbers are right-aligned in fixed units.
+/// When measurements carry a cold latency (DoH), it is shown next to the
+/// warm one.
+fn print_table(results: &[(&Provider, Measurement)], columns: &[Column], numbers: NumberFormat) {
+    let columns = &shown_columns(results, columns)[..];
+    let (header, rows) = table_cells(results, columns, numbers);
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|index| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[index].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let divider = "-".repeat(widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1));
+
+    log("\nLatency Test Results:");
+    println!("{}", format_row(&header, columns, &widths));
+    println!("{}", divider);
+    for row in &rows {
+        println!("{}", format_row(row, columns, &widths));
+    }
+    println!("{}", divider);
+    warn_unreachable(results);
+}
+
+/// Prints the table as GitHub-flavored Markdown, for pasting into issues
+/// and chats: the same columns and cells as [`print_table`], with numbers
+/// right-aligned, and the unreachable servers left out likewise.
+fn print_markdown(results: &[(&Provider, Measurement)], columns: &[Column], numbers: NumberFormat) {
+    let columns = &shown_columns(results, columns)[..];
+    let (header, rows) = table_cells(results, columns, numbers);
+    let alignments: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            if is_numeric(*column) {
+                "---:".to_string()
+            } else {
+                "---".to_string()
+            }
+        })
+        .collect();
+    for row in [&header, &alignments].into_iter().chain(&rows) {
+        let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+        println!("| {} |", cells.join(" | "));
+    }
+    warn_unreachable(results);
+}
+
+/// Escapes the characters that would end or break a Markdown table cell.
+fn markdown_cell(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace('\n', " ")
+}
+
+/// Warns about the servers that never answered, which tables leave out.
+fn warn_unreachable(results: &[(&Provider, Measurement)]) {
+    let unreachable: Vec<&str> = results
+        .iter()
+        .filter(|(_, measurement)| !measurement.reachable())
+        .map(|(provider, _)| provider.name.as_str())
+        .collect();
+    if !unreachable.is_empty() {
+        warn(&format!("no response from: {}", unreachable.join(", ")));
+    }
+}
+
+/// Heading of a table column.
+fn column_title(column: Column) -> &'static str {
+    match column {
+        Column::Name => "Name",
+        Column::Ip => "Address",
+        Column::Latency => "Latency",
+        Column::Ms => "ms",
+        Column::Cold => "Cold",
+        Column::Setup => "Setup",
+        Column::Privacy => "Privacy",
+        Column::P50 => "p50",
+        Column::P90 => "p90",
+        Column::P95 => "p95",
+        Column::P99 => "p99",
+        Column::Jitter => "Jitter",
+        Column::Loss => "Loss",
+        Column::Ttl => "TTL",
+        Column::Description => "Description",
+    }
+}
+
+/// Renders one table cell.
+fn cell(
+    column: Column,
+    provider: &Provider,
+    measurement: &Measurement,
+    numbers: NumberFormat,
+) -> String {
+    let duration = |value: Option<Duration>| {
+        value
+            .map(|value| format_duration(value, numbers))
+            .unwrap_or_default()
+    };
+    match column {
+        Column::Name => provider.name.clone(),
+        Column::Ip => provider.ip.clone(),
+        Column::Latency => duration(measurement.latency()),
+        Column::Ms => measurement
+            .latency()
+            .map(|latency| format!("{:.0}", latency.as_secs_f64() * 1000.0))
+            .unwrap_or_default(),
+        Column::Cold => duration(measurement.cold),
+        Column::Setup => duration(measurement.setup),
+        Column::Privacy => match measurement.minimizes {
+            Some(true) => "qname-min".to_string(),
+            Some(false) => "leaks qname".to_string(),
+            None => String::new(),
+        },
+        Column::P50 => duration(measurement.percentile(50)),
+        Column::P90 => duration(measurement.percentile(90)),
+        Column::P95 => duration(measurement.percentile(95)),
+        Column::P99 => duration(measurement.percentile(99)),
+        Column::Jitter => duration(measurement.jitter()),
+        Column::Loss => format!("{:.0}%", measurement.loss() * 100.0),
+        Column::Ttl => measurement
+            .ttl
+            .map(|ttl| ttl.to_string())
+            .unwrap_or_default(),
+        Column::Description => provider.description.clone(),
+    }
+}
+
+/// Whether a column holds numbers, which are right-aligned.
+fn is_numeric(column: Column) -> bool {
+    matches!(
+        column,
+        Column::Latency
+            | Column::Ms
+            | Column::Cold
+            | Column::Setup
+            | Column::P50
+            | Column::P90
+            | Column::P95
+            | Column::P99
+            | Column::Jitter
+            | Column::Loss
+            | Column::Ttl
+    )
+}
+
+/// Joins the cells of a row, padding each to its column width.
+///
+/// Numeric columns are right-aligned; a trailing text column is not padded.
+fn format_row(cells: &[String], columns: &[Column], widths: &[usize]) -> String {
+    let mut line = String::new();
+    for (index, ((cell, column), width)) in cells.iter().zip(columns).zip(widths).enumerate() {
+        if index > 0 {
+            line.push_str("  ");
+        }
+        match column {
+            _ if is_numeric(*column) => {
+                let _ = write!(line, "{:>width$}", cell, width = width);
+            }
+            _ if index + 1 == cells.len() => line.push_str(cell),
+            _ => {
+                let _ = write!(line, "{:width$}", cell, width = width);
+            }
+        }
+    }
+    line
+}
+
+/// Prints the results as CSV with a header row.
+fn print_csv(rows: &[ResultRow], numbers: NumberFormat) {
+    println!(
+        "name,ip,description,{},loss,reachable",
+        match numbers.unit {
+            Unit::Ms => "latency_ms",
+            Unit::Us => "latency_us",
+        }
+    );
+    for row in rows {
+        println!(
+            "{},{},{},{},{:.3},{}",
+            csv_field(row.name),
+            csv_field(row.ip),
+            csv_field(row.description),
+            row.latency
+                .mean()
+                .map(|latency| format!("{:.*}", numbers.precision.unwrap_or(3), latency))
+                .unwrap_or_default(),
+            row.loss,
+            row.reachable
+        );
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the results as a self-contained HTML page with a bar chart.
+///
+/// The row of `chosen`, the address of the server wisp picked, is
+/// highlighted; modes that only measure pick none.
+pub fn write_html(
+    path: &Path,
+    results: &[(&Provider, Measurement)],
+    chosen: Option<&str>,
+    precision: Option<usize>,
+) -> io::Result<()> {
+    let numbers = NumberFormat {
+        unit: Unit::Ms,
+        precision,
+    };
+    let digits = precision.unwrap_or(2);
+    let rows: Vec<ResultRow> = results
+        .iter()
+        .map(|(provider, measurement)| ResultRow::new(provider, measurement, numbers))
+        .collect();
+    let slowest = rows
+        .iter()
+        .filter_map(|row| row.latency.mean())
+        .fold(0.0, f64::max);
+    let chosen = chosen.and_then(|ip| results.iter().position(|(provider, _)| provider.ip == ip));
+
+    let mut body = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        let (latency, bar) = match row.latency.mean() {
+            Some(latency) if slowest > 0.0 => (
+                format!("{:.*} ms", digits, latency),
+                format!(
+                    "<div class=\"bar\" style=\"width: {:.1}%\"></div>",
+                    latency / slowest * 100.0
+                ),
+            ),
+            Some(latency) => (format!("{:.*} ms", digits, latency), String::new()),
+            None => ("no response".to_string(), String::new()),
+        };
+        let _ = writeln!(
+            body,
+            "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.0}%</td><td class=\"chart\">{}</td></tr>",
+            if chosen == Some(index) { " class=\"chosen\"" } else { "" },
+            html_escape(row.name),
+            html_escape(row.ip),
+            html_escape(row.description),
+            latency,
+            row.loss * 100.0,
+            bar
+        );
+    }
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>wisp DNS report</title>
+<style>
+body {{ font-family: -apple-system, sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ text-align: left; padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; }}
+td.chart {{ width: 35%; }}
+.bar {{ height: 0.9em; background: #7a9cc6; }}
+tr.chosen {{ background: #e6f4e6; font-weight: bold; }}
+tr.chosen .bar {{ background: #3c9a3c; }}
+</style>
+</head>
+<body>
+<h1>DNS latency report</h1>
+<p>Generated {}.{}</p>
+<table>
+<tr><th>Name</th><th>Address</th><th>Description</th><th>Latency</th><th>Loss</th><th></th></tr>
+{}</table>
+</body>
+</html>
+"#,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        if chosen.is_some() {
+            " The highlighted server is the one wisp picked."
+        } else {
+            ""
+        },
+        body
+    );
+    fs::write(path, page)
+}
+
+/// Escapes text for use in HTML element content.
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,63 @@
+//! Daily time windows restricting when `--watch` may change the DNS.
+
+use chrono::NaiveTime;
+use std::{fmt, time::Duration};
+
+/// Seconds in a day, for windows that wrap past midnight.
+const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+/// A daily window from `start` up to (not including) `end`, local time;
+/// `22:00-06:00` wraps past midnight.
+#[derive(Clone)]
+pub struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    /// Whether `time` falls inside the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long from `time` until the window opens, zero if it is open.
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+        let seconds = (self.start - time).num_seconds().rem_euclid(DAY_SECONDS);
+        Duration::from_secs(seconds as u64)
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Parses an `HH:MM-HH:MM` window.
+pub fn parse_window(value: &str) -> Result<Window, String> {
+    let (start, end) = value.split_once('-').ok_or("expected HH:MM-HH:MM")?;
+    let time = |text: &str| {
+        NaiveTime::parse_from_str(text.trim(), "%H:%M")
+            .map_err(|_| format!("{:?} is not a HH:MM time", text))
+    };
+    let window = Window {
+        start: time(start)?,
+        end: time(end)?,
+    };
+    if window.start == window.end {
+        return Err("the window must not start and end at the same time".to_string());
+    }
+    Ok(window)
+}
@@ -0,0 +1,180 @@
+//! User-defined ranking formulas for `--score-expr`: plain arithmetic over
+//! a server's metrics, parsed up front and evaluated per server, lower is
+//! better.
+
+use crate::measurement::Measurement;
+use std::fmt;
+
+/// A metric a formula can refer to.
+#[derive(Clone, Copy)]
+enum Metric {
+    /// Mean latency in milliseconds
+    Latency,
+    /// Jitter in milliseconds, 0 with fewer than two answers
+    Jitter,
+    /// Lost share of the probes, between 0 and 1
+    Loss,
+    /// TTL left in the ping replies, 0 when unknown
+    Ttl,
+}
+
+/// A parsed formula.
+#[derive(Clone)]
+enum Expr {
+    Number(f64),
+    Metric(Metric),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+/// A formula given with `--score-expr`, kept with its source for logs.
+#[derive(Clone)]
+pub struct ScoreExpr {
+    source: String,
+    expr: Expr,
+}
+
+impl fmt::Display for ScoreExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl ScoreExpr {
+    /// The score of a measurement, `None` if the server never answered or
+    /// the formula has no finite value for it (a division by zero, say).
+    pub fn score(&self, measurement: &Measurement) -> Option<f64> {
+        let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+        let latency = ms(measurement.latency()?);
+        let metric = |metric| match metric {
+            Metric::Latency => latency,
+            Metric::Jitter => measurement.jitter().map(ms).unwrap_or_default(),
+            Metric::Loss => measurement.loss(),
+            Metric::Ttl => measurement.ttl.map(f64::from).unwrap_or_default(),
+        };
+        Some(evaluate(&self.expr, &metric)).filter(|score| score.is_finite())
+    }
+}
+
+fn evaluate(expr: &Expr, metric: &impl Fn(Metric) -> f64) -> f64 {
+    match expr {
+        Expr::Number(value) => *value,
+        Expr::Metric(name) => metric(*name),
+        Expr::Negate(operand) => -evaluate(operand, metric),
+        Expr::Binary(op, left, right) => {
+            let (left, right) = (evaluate(left, metric), evaluate(right, metric));
+            match op {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                _ => left / right,
+            }
+        }
+    }
+}
+
+/// Parses a formula of numbers, the metrics `latency`, `jitter`, `loss` and
+/// `ttl`, `+ - * /` and parentheses, e.g. `latency + 2*jitter + 100*loss`.
+pub fn parse(source: &str) -> Result<ScoreExpr, String> {
+    let mut parser = Parser {
+        chars: source.char_indices().peekable(),
+        source,
+    };
+    let expr = parser.sum()?;
+    match parser.peek() {
+        None => Ok(ScoreExpr {
+            source: source.trim().to_string(),
+            expr,
+        }),
+        Some((at, c)) => Err(format!("unexpected {:?} at position {}", c, at + 1)),
+    }
+}
+
+/// Recursive descent over the formula, one precedence level per method.
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    source: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    /// The next character that is not whitespace, without consuming it.
+    fn peek(&mut self) -> Option<(usize, char)> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    /// Terms joined by `+` and `-`.
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some((_, op @ ('+' | '-'))) = self.peek() {
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    /// Factors joined by `*` and `/`.
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some((_, op @ ('*' | '/'))) = self.peek() {
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    /// A number, a metric, a negation or a parenthesized sum.
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            None => Err("expression ends too early".to_string()),
+            Some((_, '-')) => {
+                self.chars.next();
+                Ok(Expr::Negate(Box::new(self.factor()?)))
+            }
+            Some((at, '(')) => {
+                self.chars.next();
+                let expr = self.sum()?;
+                match self.peek() {
+                    Some((_, ')')) => {
+                        self.chars.next();
+                        Ok(expr)
+                    }
+                    _ => Err(format!("unclosed '(' at position {}", at + 1)),
+                }
+            }
+            Some((at, c)) if c.is_ascii_digit() || c == '.' => {
+                let text = self.take(at, |c| c.is_ascii_digit() || c == '.');
+                text.parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("bad number {:?} at position {}", text, at + 1))
+            }
+            Some((at, c)) if c.is_ascii_alphabetic() => {
+                let name = self.take(at, |c| c.is_ascii_alphanumeric() || c == '_');
+                let metric = match name {
+                    "latency" => Metric::Latency,
+                    "jitter" => Metric::Jitter,
+                    "loss" => Metric::Loss,
+                    "ttl" => Metric::Ttl,
+                    _ => {
+                        return Err(format!(
+                        "unknown metric {:?} at position {}, expected latency, jitter, loss or ttl",
+                        name,
+                        at + 1
+                    ))
+                    }
+                };
+                Ok(Expr::Metric(metric))
+            }
+            Some((at, c)) => Err(format!("unexpected {:?} at position {}", c, at + 1)),
+        }
+    }
+
+    /// Consumes the run of characters from `start` that match `accept`.
+    fn take(&mut self, start: usize, accept: impl Fn(char) -> bool) -> &'a str {
+        let mut end = start;
+        while let Some((at, c)) = self.chars.next_if(|(_, c)| accept(*c)) {
+            end = at + c.len_utf8();
+        }
+        &self.source[start..end]
+    }
+}
@@ -0,0 +1,115 @@
+//! Parsing of the resolver configuration reported by `scutil --dns`.
+
+use regex::Regex;
+
+/// Header that starts the scoped section of `scutil --dns` output.
+const SCOPED_HEADER: &str = "DNS configuration (for scoped queries)";
+
+/// A single `resolver #N` block from `scutil --dns`.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    /// Domain this resolver is responsible for, if it is a supplemental resolver
+    pub domain: Option<String>,
+    /// Search domains attached to this resolver
+    pub search_domains: Vec<String>,
+    /// Nameserver addresses in order of preference
+    pub nameservers: Vec<String>,
+    /// Interface name from the `if_index` line, e.g. "en0" or "utun3"
+    pub interface: Option<String>,
+    /// Whether the resolver was listed in the scoped queries section
+    pub scoped: bool,
+}
+
+impl Resolver {
+    /// Returns true if this resolver serves the given domain, either as its
+    /// supplemental `domain` or as one of its search domains.
+    pub fn serves(&self, scope: &str) -> bool {
+        let scope = scope.trim_end_matches('.');
+        self.domain
+            .iter()
+            .chain(&self.search_domains)
+            .any(|domain| domain.trim_end_matches('.').eq_ignore_ascii_case(scope))
+    }
+}
+
+/// Parses the full output of `scutil --dns` into its resolver blocks.
+///
+/// Both the regular and the scoped sections are parsed; resolvers from the
+/// latter are marked with [`Resolver::scoped`].
+pub fn parse_dns_output(output: &str) -> Vec<Resolver> {
+    let entry_pattern = Regex::new(r"^\s*([a-z_ ]+?)(?:\[\d+\])?\s*:\s*(.+?)\s*$").unwrap();
+    let interface_pattern = Regex::new(r"\((.+)\)").unwrap();
+
+    let mut resolvers: Vec<Resolver> = Vec::new();
+    let mut scoped = false;
+
+    for line in output.lines() {
+        if line.starts_with(SCOPED_HEADER) {
+            scoped = true;
+            continue;
+        }
+        if line.starts_with("resolver #") {
+            resolvers.push(Resolver {
+                scoped,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let (Some(resolver), Some(cap)) = (resolvers.last_mut(), entry_pattern.captures(line))
+        else {
+            continue;
+        };
+        let value = cap[2].to_string();
+        match &cap[1] {
+            "domain" => resolver.domain = Some(value),
+            "search domain" => resolver.search_domains.push(value),
+            "nameserver" => resolver.nameservers.push(value),
+            "if_index" => {
+                resolver.interface = interface_pattern
+                    .captures(&value)
+                    .map(|cap| cap[1].to_string())
+            }
+            _ => {}
+        }
+    }
+
+    resolvers
+}
+
+/// Parses the output of `scutil --proxy` into a description of every enabled
+/// proxy mechanism, e.g. "HTTP proxy proxy.corp:8080" or "PAC file <url>".
+///
+/// Returns an empty list when no proxy is configured.
+pub fn parse_proxy_output(output: &str) -> Vec<String> {
+    let entry_pattern = Regex::new(r"^\s*(\w+)\s*:\s*(.+?)\s*$").unwrap();
+    let settings: Vec<(String, String)> = output
+        .lines()
+        .filter_map(|line| entry_pattern.captures(line))
+        .map(|cap| (cap[1].to_string(), cap[2].to_string()))
+        .collect();
+    let get = |key: &str| {
+        settings
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    };
+    let enabled = |key: &str| get(key) == Some("1");
+
+    let mut proxies = Vec::new();
+    for (kind, prefix) in [("HTTP", "HTTP"), ("HTTPS", "HTTPS"), ("SOCKS", "SOCKS")] {
+        if enabled(&format!("{}Enable", prefix)) {
+            let host = get(&format!("{}Proxy", prefix)).unwrap_or("?");
+            let port = get(&format!("{}Port", prefix)).unwrap_or("?");
+            proxies.push(format!("{} proxy {}:{}", kind, host, port));
+        }
+    }
+    if enabled("ProxyAutoConfigEnable") {
+        let url = get("ProxyAutoConfigURLString").unwrap_or("?");
+        proxies.push(format!("PAC file {}", url));
+    }
+    if enabled("ProxyAutoDiscoveryEnable") {
+        proxies.push("proxy auto-discovery (WPAD)".to_string());
+    }
+    proxies
+}
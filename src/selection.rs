@@ -0,0 +1,266 @@
+//! Ways of choosing the server to apply from the measured results, selected
+//! with `--strategy`.
+
+use crate::{
+    cli::{Cli, Strategy},
+    measurement::Measurement,
+    providers::Provider,
+    score::ScoreExpr,
+};
+use std::time::Duration;
+
+/// Latency added to a server's score with `composite` for losing every probe.
+const LOSS_PENALTY: Duration = Duration::from_millis(500);
+
+/// A way of ranking measured servers to decide which one gets applied.
+pub trait SelectionStrategy {
+    /// How servers are ordered, shown in the `--verbose` configuration.
+    fn describe(&self) -> String;
+
+    /// Sorts `results` best first. Servers that never answered always sort
+    /// last.
+    fn rank(&self, results: &mut [(&Provider, Measurement)]);
+
+    /// Ranks `results` and returns the servers that may be applied, best
+    /// first: every reachable one except baselines.
+    fn select<'a>(&self, results: &mut [(&'a Provider, Measurement)]) -> Vec<&'a Provider> {
+        self.rank(results);
+        results
+            .iter()
+            .filter(|(provider, measurement)| !provider.baseline && measurement.reachable())
+            .map(|(provider, _)| *provider)
+            .collect()
+    }
+}
+
+/// Returns the strategy chosen on the command line; `--sticky` is short for
+/// `--strategy sticky`. `current` are the servers configured before the run.
+pub fn from_cli(cli: &Cli, current: Vec<String>) -> Box<dyn SelectionStrategy> {
+    if let Some(expr) = &cli.score_expr {
+        return Box::new(Formula { expr: expr.clone() });
+    }
+    match strategy(cli) {
+        Strategy::Fastest => match cli.rank_percentile {
+            Some(percentile) => Box::new(FastestPercentile { percentile }),
+            None => Box::new(FastestLatency),
+        },
+        Strategy::LowestLoss => Box::new(LowestLoss),
+        Strategy::Composite => Box::new(BestComposite),
+        Strategy::Sticky => Box::new(Sticky {
+            current,
+            bonus: Duration::from_millis(cli.sticky_bonus),
+        }),
+        Strategy::PreferNamed => Box::new(PreferNamed {
+            names: cli.prefer.iter().map(|name| name.to_lowercase()).collect(),
+        }),
+    }
+}
+
+/// The strategy in effect, taking `--sticky` into account.
+pub fn strategy(cli: &Cli) -> Strategy {
+    if cli.sticky {
+        Strategy::Sticky
+    } else {
+        cli.strategy
+    }
+}
+
+/// Sorts results by latency minus the `bonus` granted to each provider.
+///
+/// Servers that never answered always sort last.
+pub fn rank_by_latency(
+    results: &mut [(&Provider, Measurement)],
+    bonus: impl Fn(&Provider) -> Duration,
+) {
+    results.sort_by_key(|(provider, measurement)| {
+        let latency = measurement
+            .latency()
+            .map(|latency| latency.saturating_sub(bonus(provider)));
+        (latency.is_none(), latency)
+    });
+}
+
+/// The server with the lowest mean latency.
+struct FastestLatency;
+
+impl SelectionStrategy for FastestLatency {
+    fn describe(&self) -> String {
+        "mean latency".to_string()
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        rank_by_latency(results, |_| Duration::ZERO);
+    }
+}
+
+/// The server with the lowest latency at a percentile (`--rank-percentile`),
+/// favoring servers whose slow probes are rare over ones with a low mean.
+struct FastestPercentile {
+    percentile: u8,
+}
+
+impl SelectionStrategy for FastestPercentile {
+    fn describe(&self) -> String {
+        format!("p{} latency", self.percentile)
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        results.sort_by_key(|(_, measurement)| {
+            let latency = measurement.percentile(self.percentile);
+            (latency.is_none(), latency)
+        });
+    }
+}
+
+/// The server with the lowest `--score-expr` score.
+struct Formula {
+    expr: ScoreExpr,
+}
+
+impl SelectionStrategy for Formula {
+    fn describe(&self) -> String {
+        format!("score {}", self.expr)
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        results.sort_by(|(_, a), (_, b)| {
+            let (a, b) = (self.expr.score(a), self.expr.score(b));
+            (a.is_none(), a)
+                .partial_cmp(&(b.is_none(), b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// The server that lost the fewest probes, ties broken by latency.
+struct LowestLoss;
+
+impl SelectionStrategy for LowestLoss {
+    fn describe(&self) -> String {
+        "packet loss, then mean latency".to_string()
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        results.sort_by(|(_, a), (_, b)| {
+            (a.latency().is_none(), a.loss(), a.latency())
+                .partial_cmp(&(b.latency().is_none(), b.loss(), b.latency()))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// The server with the best mix of latency, jitter and loss: its mean
+/// latency plus its jitter plus [`LOSS_PENALTY`] scaled by the lost share.
+struct BestComposite;
+
+impl SelectionStrategy for BestComposite {
+    fn describe(&self) -> String {
+        format!("mean latency + jitter + {:?} per 100% loss", LOSS_PENALTY)
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        results.sort_by_key(|(_, measurement)| {
+            let score = measurement.latency().map(|latency| {
+                latency
+                    + measurement.jitter().unwrap_or_default()
+                    + LOSS_PENALTY.mul_f64(measurement.loss())
+            });
+            (score.is_none(), score)
+        });
+    }
+}
+
+/// The lowest latency after the current servers get a bonus, so the tool only
+/// switches for a clearly better alternative.
+struct Sticky {
+    current: Vec<String>,
+    bonus: Duration,
+}
+
+impl SelectionStrategy for Sticky {
+    fn describe(&self) -> String {
+        format!(
+            "mean latency, current servers favored by {}ms",
+            self.bonus.as_millis()
+        )
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        rank_by_latency(results, |provider| {
+            if self.current.contains(&provider.ip) {
+                self.bonus
+            } else {
+                Duration::ZERO
+            }
+        });
+    }
+}
+
+/// Servers named with `--prefer` (by name or provider family) first, each
+/// group by latency; other servers are only chosen when none of them answered.
+struct PreferNamed {
+    names: Vec<String>,
+}
+
+impl PreferNamed {
+    fn preferred(&self, provider: &Provider) -> bool {
+        self.names.contains(&provider.name.to_lowercase())
+            || self.names.contains(&provider.family.to_lowercase())
+    }
+}
+
+impl SelectionStrategy for PreferNamed {
+    fn describe(&self) -> String {
+        format!("preferring {}, then mean latency", self.names.join(", "))
+    }
+
+    fn rank(&self, results: &mut [(&Provider, Measurement)]) {
+        results.sort_by_key(|(provider, measurement)| {
+            let latency = measurement.latency();
+            (latency.is_none(), !self.preferred(provider), latency)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use clap::Parser;
+
+    const LATENCIES: &str = "\
+        1.1.1.1 20 22 21\n\
+        8.8.8.8 10 11 60\n\
+        9.9.9.9 - 12 14\n";
+
+    /// The address `args` would apply given [`LATENCIES`].
+    fn winner(args: &[&str]) -> String {
+        let mock = mock::parse(LATENCIES).unwrap();
+        let providers: Vec<Provider> = ["1.1.1.1", "8.8.8.8", "9.9.9.9"]
+            .iter()
+            .map(|ip| Provider::new(ip, ip, "test"))
+            .collect();
+        let mut results: Vec<_> = providers
+            .iter()
+            .map(|provider| (provider, mock.measurement(&provider.ip)))
+            .collect();
+        let cli = Cli::parse_from(std::iter::once("wisp").chain(args.iter().copied()));
+        let candidates = from_cli(&cli, Vec::new()).select(&mut results);
+        candidates[0].ip.clone()
+    }
+
+    #[test]
+    fn lowest_mean_wins() {
+        assert_eq!(winner(&[]), "9.9.9.9");
+    }
+
+    #[test]
+    fn lossless_server_wins_on_loss() {
+        assert_eq!(winner(&["--strategy", "lowest-loss"]), "1.1.1.1");
+    }
+
+    #[test]
+    fn median_beats_one_slow_probe() {
+        assert_eq!(winner(&["--rank-percentile", "50"]), "8.8.8.8");
+    }
+}
@@ -0,0 +1,50 @@
+//! Exponentially weighted moving averages of server latencies, so
+//! `--watch --smoothing` ranks on a trend instead of one noisy cycle.
+
+use crate::{measurement::Measurement, providers::Provider};
+use std::{collections::HashMap, time::Duration};
+
+/// Pings per server in the lighter cycles that follow the first one.
+pub const SMOOTHED_PING_COUNT: usize = 1;
+
+/// Smoothed latency of every server seen so far, keyed by address.
+pub struct Ewma {
+    /// Weight of the newest cycle, between 0 (never move) and 1 (no smoothing)
+    alpha: f64,
+    latencies: HashMap<String, Duration>,
+}
+
+impl Ewma {
+    pub fn new(alpha: f64) -> Self {
+        Ewma {
+            alpha,
+            latencies: HashMap::new(),
+        }
+    }
+
+    /// Whether no cycle has been folded in yet, i.e. the next one should be a
+    /// full measurement to seed the averages.
+    pub fn is_empty(&self) -> bool {
+        self.latencies.is_empty()
+    }
+
+    /// Folds this cycle's latencies into the averages, then shifts the samples
+    /// of every answering server so their mean is the smoothed latency; the
+    /// spread (jitter) and loss of the cycle stay as measured. Servers that
+    /// did not answer keep their average for the next cycle.
+    pub fn update(&mut self, results: &mut [(&Provider, Measurement)]) {
+        for (provider, measurement) in results.iter_mut() {
+            let Some(latency) = measurement.latency() else {
+                continue;
+            };
+            let smoothed = match self.latencies.get(&provider.ip) {
+                Some(previous) => previous.mul_f64(1.0 - self.alpha) + latency.mul_f64(self.alpha),
+                None => latency,
+            };
+            self.latencies.insert(provider.ip.clone(), smoothed);
+            for sample in &mut measurement.samples {
+                *sample = (*sample + smoothed).saturating_sub(latency);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! The local address every probe is sent from, set with `--source` to
+//! compare the paths of a multihomed machine (VPN against Wi-Fi, say), and
+//! on Linux the VRF whose routing table the probes follow (`--vrf`).
+
+use socket2::{Domain, Socket, Type};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    sync::OnceLock,
+    time::Duration,
+};
+
+/// Source address chosen at startup, `None` to let the system pick.
+static SOURCE: OnceLock<Option<IpAddr>> = OnceLock::new();
+
+/// VRF device chosen at startup, `None` for the main routing table.
+static VRF: OnceLock<Option<String>> = OnceLock::new();
+
+/// Sets the source address and VRF of all probes. Only the first call has
+/// an effect.
+pub fn init(source: Option<IpAddr>, vrf: Option<String>) {
+    let _ = SOURCE.set(source);
+    let _ = VRF.set(vrf);
+}
+
+/// The VRF device given with `--vrf`, if any.
+pub fn vrf() -> Option<&'static str> {
+    VRF.get().and_then(Option::as_deref)
+}
+
+/// Binds a socket to the `--vrf` device, so its traffic is routed through
+/// that VRF's table. Does nothing without one.
+#[cfg(target_os = "linux")]
+fn bind_vrf(socket: &impl std::os::fd::AsRawFd) -> io::Result<()> {
+    let Some(vrf) = vrf() else {
+        return Ok(());
+    };
+    // SAFETY: the option value is `vrf`'s bytes with their exact length,
+    // on a socket that stays open for the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            vrf.as_ptr().cast(),
+            vrf.len() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Only Linux has VRFs, and `--vrf` is refused elsewhere.
+#[cfg(not(target_os = "linux"))]
+fn bind_vrf<T>(_socket: &T) -> io::Result<()> {
+    Ok(())
+}
+
+/// The source address given with `--source`, if any.
+pub fn address() -> Option<IpAddr> {
+    SOURCE.get().copied().flatten()
+}
+
+/// The local address to bind to for talking to `server`: the source
+/// address, or any address of the server's family.
+fn local(server: IpAddr) -> SocketAddr {
+    let ip = address().unwrap_or(match server {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    SocketAddr::new(ip, 0)
+}
+
+/// A UDP socket bound to the source address, ready to talk to `server`.
+pub fn udp_socket(server: IpAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(local(server))?;
+    bind_vrf(&socket)?;
+    Ok(socket)
+}
+
+/// Opens a TCP connection to `target` from the source address, through
+/// the VRF.
+pub fn tcp_connect(target: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    if address().is_none() && vrf().is_none() {
+        return TcpStream::connect_timeout(&target, timeout);
+    }
+    let socket = Socket::new(Domain::for_address(target), Type::STREAM, None)?;
+    socket.bind(&local(target.ip()).into())?;
+    bind_vrf(&socket)?;
+    socket.connect_timeout(&target.into(), timeout)?;
+    Ok(socket.into())
+}
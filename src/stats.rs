@@ -0,0 +1,63 @@
+//! Statistics over latency samples.
+
+use std::time::Duration;
+
+/// Mean of the samples in milliseconds, `None` for an empty slice.
+pub fn mean_ms(samples: &[Duration]) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(samples.iter().map(as_ms).sum::<f64>() / samples.len() as f64)
+}
+
+/// Sample variance in squared milliseconds, `None` with fewer than two samples.
+pub fn variance_ms(samples: &[Duration]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let mean = mean_ms(samples)?;
+    let sum: f64 = samples.iter().map(|s| (as_ms(s) - mean).powi(2)).sum();
+    Some(sum / (samples.len() - 1) as f64)
+}
+
+/// Two-sided p-value of Welch's t-test for a difference in mean latency.
+///
+/// Uses the normal approximation of the t distribution, which is close
+/// enough for the sample sizes of a head-to-head comparison. Returns `None`
+/// if either side has fewer than two samples.
+pub fn welch_p_value(a: &[Duration], b: &[Duration]) -> Option<f64> {
+    let (mean_a, mean_b) = (mean_ms(a)?, mean_ms(b)?);
+    let standard_error =
+        (variance_ms(a)? / a.len() as f64 + variance_ms(b)? / b.len() as f64).sqrt();
+    if standard_error == 0.0 {
+        return Some(if mean_a == mean_b { 1.0 } else { 0.0 });
+    }
+    let t = (mean_a - mean_b).abs() / standard_error;
+    Some(erfc(t / std::f64::consts::SQRT_2))
+}
+
+/// The `percentile`th percentile of the samples (nearest rank), e.g. 95 for
+/// the latency 95% of the probes beat; `None` for an empty slice.
+pub fn percentile(samples: &[Duration], percentile: u8) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let rank = (percentile as usize * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank.min(sorted.len()) - 1])
+}
+
+fn as_ms(duration: &Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Complementary error function (Abramowitz and Stegun 7.1.26, error below
+/// 1.5e-7), valid for `x >= 0`.
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    poly * (-x * x).exp()
+}
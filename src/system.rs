@@ -0,0 +1,697 @@
+//! Reading and changing the macOS DNS configuration through `scutil` and
+//! `networksetup`.
+
+use crate::{
+    error::Error,
+    logging::{error, log, warn},
+    scutil::{parse_dns_output, parse_proxy_output, Resolver},
+};
+use std::{
+    fmt, fs,
+    io::{self, IsTerminal, Read, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Directory of per-domain resolver files, see resolver(5).
+const RESOLVER_DIR: &str = "/etc/resolver";
+
+/// First line of the resolver files wisp writes, so it only ever removes
+/// its own.
+const RESOLVER_MARKER: &str = "# Written by wisp";
+
+/// Where macOS stores the network configuration that is loaded at boot.
+const PREFERENCES_FILE: &str = "/Library/Preferences/SystemConfiguration/preferences.plist";
+
+/// Network service whose DNS servers are read and changed.
+pub const SERVICE: &str = "Wi-Fi";
+
+/// Pause after changing DNS before reading it back, set once at startup.
+static SETTLE_DELAY: OnceLock<Duration> = OnceLock::new();
+
+/// How many more times applied servers are read back before giving up on
+/// them, set once at startup.
+static VERIFY_RETRIES: OnceLock<u32> = OnceLock::new();
+
+/// Verification retries when `--verify-retries` is not given.
+pub const DEFAULT_VERIFY_RETRIES: u32 = 2;
+
+/// How long a system command may run before it is considered stuck.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often a running command is checked for having finished.
+const COMMAND_POLL: Duration = Duration::from_millis(10);
+
+/// Settle delay for interactive runs.
+pub const SETTLE_DELAY_INTERACTIVE: Duration = Duration::from_secs(2);
+
+/// Settle delay for scripted runs, where a shorter disruption matters more.
+pub const SETTLE_DELAY_SCRIPTED: Duration = Duration::from_millis(500);
+
+/// Sets how long to wait for DNS changes to take effect and how often to
+/// re-check them. Only the first call has an effect.
+pub fn init(settle_delay: Duration, verify_retries: u32) {
+    let _ = SETTLE_DELAY.set(settle_delay);
+    let _ = VERIFY_RETRIES.set(verify_retries);
+}
+
+/// Whether a person is watching the run, judged by stderr being a terminal;
+/// cron, launchd and pipelines are not interactive.
+pub fn is_interactive() -> bool {
+    io::stderr().is_terminal()
+}
+
+/// Waits for a DNS change to take effect.
+fn settle() {
+    thread::sleep(
+        SETTLE_DELAY
+            .get()
+            .copied()
+            .unwrap_or(SETTLE_DELAY_INTERACTIVE),
+    );
+}
+
+/// Runs a command to completion, failing if it could not be started, exited
+/// with a non-zero status or did not finish within [`COMMAND_TIMEOUT`].
+///
+/// The command gets no stdin, so one that wants input (such as an
+/// authorization prompt on managed Macs) fails or times out instead of
+/// hanging the run.
+pub fn run(program: &str, args: &[&str]) -> Result<Output, Error> {
+    run_with_input(program, args, None)
+}
+
+/// Runs a command like [`run`], writing `input` to its stdin first: the way
+/// to hand it a secret, which in the arguments anyone could read with `ps`.
+pub fn run_with_input(program: &str, args: &[&str], input: Option<&str>) -> Result<Output, Error> {
+    let command = format!("{} {}", program, args.join(" "));
+    let failed = |message: String| Error::Command {
+        command: command.clone(),
+        message,
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| failed(err.to_string()))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // Dropping stdin afterwards closes it, so the command sees the end
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    // Drained on their own threads so a chatty command cannot block on a
+    // full pipe while it is being waited for
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            bytes
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + COMMAND_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|err| failed(err.to_string()))? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(failed(format!(
+                "no answer after {}s, it may be waiting for authorization; run wisp with sudo or allow it in the device management profile",
+                COMMAND_TIMEOUT.as_secs()
+            )));
+        }
+        thread::sleep(COMMAND_POLL);
+    };
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::Command {
+            command,
+            message: if stderr.is_empty() {
+                output.status.to_string()
+            } else {
+                stderr
+            },
+        });
+    }
+    Ok(output)
+}
+
+/// Retrieves every resolver the system reports.
+///
+/// Uses the `scutil` command to query DNS settings and parses the output
+/// into its individual resolver blocks. When scutil fails, a warning is
+/// logged and no resolvers are returned.
+pub fn get_resolvers() -> Vec<Resolver> {
+    match run("scutil", &["--dns"]) {
+        Ok(output) => parse_dns_output(&String::from_utf8_lossy(&output.stdout)),
+        Err(err) => {
+            warn(&format!("cannot read the DNS configuration: {}", err));
+            Vec::new()
+        }
+    }
+}
+
+/// Retrieves the current DNS server configuration from the system.
+///
+/// Only resolvers outside the scoped queries section are considered.
+pub fn get_current_dns() -> Vec<String> {
+    get_resolvers()
+        .into_iter()
+        .filter(|resolver| !resolver.scoped)
+        .flat_map(|resolver| resolver.nameservers)
+        .collect()
+}
+
+/// Retrieves the resolvers responsible for a scope (a supplemental or
+/// search domain), such as the split-DNS resolver pushed by a VPN.
+pub fn get_scope_resolvers(scope: &str) -> Vec<Resolver> {
+    get_resolvers()
+        .into_iter()
+        .filter(|resolver| resolver.serves(scope) && !resolver.nameservers.is_empty())
+        .collect()
+}
+
+/// Retrieves the enabled system proxy mechanisms (HTTP/HTTPS/SOCKS proxies,
+/// PAC files, auto-discovery) from `scutil --proxy`.
+///
+/// Detection is best effort: an empty list is returned if scutil fails.
+pub fn get_proxies() -> Vec<String> {
+    run("scutil", &["--proxy"])
+        .map(|output| parse_proxy_output(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+/// Checks whether the system has a default route, i.e. any network at all.
+///
+/// Assumes a connection if `route` cannot be run, so a missing tool never
+/// blocks a run.
+pub fn has_default_route() -> bool {
+    match run("route", &["-n", "get", "default"]) {
+        Ok(_) => true,
+        // What route reports when there is no default route to get
+        Err(Error::Command { message, .. }) => !message.contains("not in table"),
+        Err(_) => true,
+    }
+}
+
+/// The default route: which network the machine is on.
+#[derive(Clone, PartialEq)]
+pub struct Route {
+    /// Device it goes through, e.g. `en0` or `utun3` for a VPN
+    pub interface: String,
+    /// Router it goes to, `None` for point-to-point links
+    pub gateway: Option<String>,
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.gateway {
+            Some(gateway) => write!(f, "{} via {}", self.interface, gateway),
+            None => f.write_str(&self.interface),
+        }
+    }
+}
+
+/// Returns the default route, `None` without one or when `route` cannot
+/// be run.
+pub fn default_route() -> Option<Route> {
+    let output = run("route", &["-n", "get", "default"]).ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(name))
+            .map(|value| value.trim().to_string())
+    };
+    Some(Route {
+        interface: field("interface:")?,
+        gateway: field("gateway:"),
+    })
+}
+
+/// Returns the device (e.g. `en0`) the default route goes through.
+pub fn default_interface() -> Option<String> {
+    default_route().map(|route| route.interface)
+}
+
+/// Returns the DNS servers the DHCP server handed out on a device, read
+/// from the lease with `ipconfig getpacket`, whatever DNS is set to now.
+pub fn dhcp_dns(device: &str) -> Result<Vec<String>, Error> {
+    let output = run("ipconfig", &["getpacket", device])?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("domain_name_server (ip_mult):"))
+        .map(|servers| {
+            servers
+                .trim()
+                .trim_start_matches('{')
+                .trim_end_matches('}')
+                .split(',')
+                .map(|server| server.trim().to_string())
+                .filter(|server| !server.is_empty())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Returns the IPv4 address of a device (e.g. `utun3`) as reported by
+/// `ipconfig getifaddr`.
+pub fn interface_address(device: &str) -> Result<IpAddr, Error> {
+    let output = run("ipconfig", &["getifaddr", device])?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.trim().parse().map_err(|_| Error::Command {
+        command: format!("ipconfig getifaddr {}", device),
+        message: format!("no address in {:?}", text.trim()),
+    })
+}
+
+/// Lists the network services known to `networksetup`, disabled ones
+/// included.
+pub fn list_services() -> Result<Vec<String>, Error> {
+    let output = run("networksetup", &["-listallnetworkservices"])?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .map(|line| line.trim_start_matches('*').trim().to_string())
+        .filter(|service| !service.is_empty())
+        .collect())
+}
+
+/// Returns the hardware port (which names the network service) of a device.
+pub fn hardware_port(device: &str) -> Option<String> {
+    let output = run("networksetup", &["-listallhardwareports"]).ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut port = None;
+    for line in text.lines() {
+        if let Some(name) = line.strip_prefix("Hardware Port:") {
+            port = Some(name.trim().to_string());
+        } else if line.strip_prefix("Device:").map(str::trim) == Some(device) {
+            return port;
+        }
+    }
+    None
+}
+
+/// Retrieves the DNS servers manually configured for the Wi-Fi interface.
+///
+/// Unlike [`get_current_dns`], which reports whatever the resolver is using,
+/// this returns an empty list when DNS is on automatic (DHCP), so the result
+/// can be handed back to [`restore_dns`].
+pub fn get_manual_dns() -> Result<Vec<String>, Error> {
+    let output = run("networksetup", &["-getdnsservers", SERVICE])?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    if text.contains("There aren't any DNS Servers set") {
+        return Ok(Vec::new());
+    }
+    Ok(text.split_whitespace().map(str::to_string).collect())
+}
+
+/// Reads the DNS servers of the Wi-Fi service from the saved configuration
+/// that is loaded at boot, rather than from the running system, to tell a
+/// durable change from one that only lives until the next reboot. Empty
+/// when the saved configuration is automatic (DHCP).
+pub fn get_persistent_dns() -> Result<Vec<String>, Error> {
+    let output = run(
+        "plutil",
+        &[
+            "-extract",
+            "NetworkServices",
+            "json",
+            "-o",
+            "-",
+            PREFERENCES_FILE,
+        ],
+    )?;
+    let fail = |message: &str| Error::Command {
+        command: format!("plutil -extract NetworkServices {}", PREFERENCES_FILE),
+        message: message.to_string(),
+    };
+    let services: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|err| fail(&err.to_string()))?;
+    let service = services
+        .as_object()
+        .and_then(|services| {
+            services
+                .values()
+                .find(|service| service["UserDefinedName"] == SERVICE)
+        })
+        .ok_or_else(|| {
+            fail(&format!(
+                "no {} service in the saved configuration",
+                SERVICE
+            ))
+        })?;
+    Ok(service["DNS"]["ServerAddresses"]
+        .as_array()
+        .map(|servers| {
+            servers
+                .iter()
+                .filter_map(|server| server.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Sets the DNS servers for the Wi-Fi interface.
+///
+/// * Waits for the settle delay (2 seconds interactively) after setting DNS to
+///   allow changes to take effect
+/// * Only affects the Wi-Fi interface
+/// * Reads the servers back afterwards, retrying a few times (see `init`);
+///   if they still did not stick, puts the previous servers back and fails
+pub fn set_dns(servers: &[String]) -> Result<(), Error> {
+    apply(&Changes {
+        servers,
+        split_dns: Vec::new(),
+        remove_split_dns: &[],
+        previous: None,
+    })
+}
+
+/// Configuration changes made together by [`apply`].
+pub struct Changes<'a> {
+    /// New servers of the Wi-Fi service
+    pub servers: &'a [String],
+    /// Domains sent to their own servers, see [`set_split_dns`]
+    pub split_dns: Vec<(&'a str, &'a [String])>,
+    /// Domains whose split DNS is taken out, see [`remove_split_dns`]
+    pub remove_split_dns: &'a [String],
+    /// Servers put back if the new ones do not stick, `None` for the ones
+    /// set when applying; a run that reset DNS to automatic for its tests
+    /// passes the ones from before the reset
+    pub previous: Option<&'a [String]>,
+}
+
+/// Makes every change first and then waits and verifies once, instead of
+/// paying a settle delay per change. The servers are verified as in
+/// [`set_dns`], previous ones restored if they did not stick; split DNS
+/// that is not active yet by then is only warned about, since resolver
+/// files are picked up on the system's own schedule.
+pub fn apply(changes: &Changes) -> Result<(), Error> {
+    let servers = changes.servers;
+    let previous = match changes.previous {
+        Some(previous) => previous.to_vec(),
+        None => get_manual_dns()?,
+    };
+    log(&format!("Setting DNS servers to: {}", servers.join(", ")));
+    write_dns(servers)?;
+    for (domain, split_servers) in &changes.split_dns {
+        set_split_dns(domain, split_servers)?;
+    }
+    for domain in changes.remove_split_dns {
+        remove_split_dns(domain)?;
+    }
+
+    // One settle and verification pass covers every change above
+    if let Err(err) = verify_dns_retrying(servers) {
+        error(&format!(
+            "Giving up on {}, restoring previous DNS: {}",
+            servers.join(", "),
+            describe_servers(&previous)
+        ));
+        write_dns(&previous)?;
+        verify_dns_retrying(&previous)?;
+        return Err(err);
+    }
+    for (domain, split_servers) in &changes.split_dns {
+        let active = get_scope_resolvers(domain)
+            .iter()
+            .any(|resolver| resolver.nameservers == *split_servers);
+        if !active {
+            warn(&format!(
+                "split DNS for {} is written but not active yet",
+                domain
+            ));
+        }
+    }
+    log("DNS settings applied");
+    Ok(())
+}
+
+/// Hands `servers` to `networksetup`, automatic (DHCP) mode when empty.
+fn write_dns(servers: &[String]) -> Result<(), Error> {
+    run("networksetup", &dns_args(servers)).map(drop)
+}
+
+/// Arguments of the `networksetup` call that sets `servers`.
+fn dns_args(servers: &[String]) -> Vec<&str> {
+    let mut args = vec!["-setdnsservers", SERVICE];
+    if servers.is_empty() {
+        args.push("empty");
+    } else {
+        args.extend(servers.iter().map(String::as_str));
+    }
+    args
+}
+
+/// The shell commands that would make the changes, as [`apply`] would make
+/// them, for `--print-commands`; `reset` puts the reset to automatic that
+/// precedes the tests first. Resolver files are shown as the commands that
+/// write or remove the same files, and the read-backs that verify the
+/// changes are listed as comments.
+pub fn commands(changes: &Changes, reset: bool) -> Vec<String> {
+    let shell = |program: &str, args: &[&str]| {
+        std::iter::once(program)
+            .chain(args.iter().copied())
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let mut commands = Vec::new();
+    if reset {
+        commands.push("# before the tests".to_string());
+        commands.push(shell("networksetup", &dns_args(&[])));
+        commands.push("# after them".to_string());
+    }
+    commands.push(shell("networksetup", &dns_args(changes.servers)));
+    if !changes.split_dns.is_empty() {
+        commands.push(shell("mkdir", &["-p", RESOLVER_DIR]));
+    }
+    for (domain, servers) in &changes.split_dns {
+        let (path, contents) = resolver_file(domain, servers);
+        let format = contents.replace('%', "%%").replace('\n', "\\n");
+        commands.push(format!(
+            "printf {} > {}",
+            shell_quote(&format),
+            shell_quote(&path.display().to_string())
+        ));
+    }
+    for domain in changes.remove_split_dns {
+        let path = Path::new(RESOLVER_DIR).join(domain);
+        match fs::read_to_string(&path) {
+            Ok(contents) if contents.starts_with(RESOLVER_MARKER) => {
+                commands.push(shell("rm", &[&path.display().to_string()]));
+            }
+            Ok(_) => commands.push(format!("# {} is not wisp's, left alone", path.display())),
+            Err(_) => commands.push(format!("# no split DNS configured for {}", domain)),
+        }
+    }
+    commands.push(format!(
+        "# then read back with: {}",
+        shell("networksetup", &["-getdnsservers", SERVICE])
+    ));
+    commands
+}
+
+/// Quotes a word for the shell when it has anything but plain characters.
+fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:%,=@".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Names a configuration in log messages.
+fn describe_servers(servers: &[String]) -> String {
+    if servers.is_empty() {
+        "automatic".to_string()
+    } else {
+        servers.join(", ")
+    }
+}
+
+/// Sets DNS configuration to automatic (DHCP) mode.
+///
+/// This removes any manually configured DNS servers and allows
+/// the system to obtain DNS settings automatically from DHCP. Search
+/// domains are a separate setting that `-setdnsservers` leaves alone, the
+/// manual ones as well as those DHCP hands out (option 119), so there is
+/// nothing of them to snapshot or restore.
+pub fn set_dns_automatic() -> Result<(), Error> {
+    log("Setting DNS to automatic (empty)");
+    write_dns(&[])?;
+    verify_dns_retrying(&[])?;
+    log("DNS set to automatic mode");
+    Ok(())
+}
+
+/// Sends queries for `domain` and its subdomains to `servers` by writing a
+/// resolver file, leaving the system-wide servers alone.
+pub fn set_split_dns(domain: &str, servers: &[String]) -> Result<(), Error> {
+    log(&format!(
+        "Setting split DNS for {} to: {}",
+        domain,
+        servers.join(", ")
+    ));
+    let (path, contents) = resolver_file(domain, servers);
+    fs::create_dir_all(RESOLVER_DIR)
+        .and_then(|()| fs::write(&path, contents))
+        .map_err(|err| Error::Write {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })
+}
+
+/// Path and contents of the resolver file sending `domain` to `servers`.
+fn resolver_file(domain: &str, servers: &[String]) -> (PathBuf, String) {
+    let mut contents = format!("{}\n", RESOLVER_MARKER);
+    for server in servers {
+        contents.push_str(&format!("nameserver {}\n", server));
+    }
+    (Path::new(RESOLVER_DIR).join(domain), contents)
+}
+
+/// Removes a resolver file written by [`set_split_dns`]. Files wisp did not
+/// write are left alone.
+pub fn remove_split_dns(domain: &str) -> Result<(), Error> {
+    let path = Path::new(RESOLVER_DIR).join(domain);
+    match fs::read_to_string(&path) {
+        Ok(contents) if contents.starts_with(RESOLVER_MARKER) => {
+            log(&format!("Removing split DNS for {}", domain));
+            fs::remove_file(&path).map_err(|err| Error::Write {
+                path: path.display().to_string(),
+                message: err.to_string(),
+            })
+        }
+        Ok(_) => {
+            warn(&format!(
+                "{} was not written by wisp, leaving it alone",
+                path.display()
+            ));
+            Ok(())
+        }
+        Err(_) => {
+            warn(&format!("no split DNS configured for {}", domain));
+            Ok(())
+        }
+    }
+}
+
+/// Puts back a configuration captured with [`get_manual_dns`].
+pub fn restore_dns(servers: &[String]) -> Result<(), Error> {
+    if servers.is_empty() {
+        set_dns_automatic()
+    } else {
+        set_dns(servers)
+    }
+}
+
+/// Waits for a change to settle and checks that it took, re-checking after
+/// another settle delay up to the configured number of retries, since the
+/// system sometimes reports the old servers for a moment.
+fn verify_dns_retrying(expected: &[String]) -> Result<(), Error> {
+    let attempts = VERIFY_RETRIES
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_VERIFY_RETRIES)
+        + 1;
+    let mut attempt = 1;
+    loop {
+        settle();
+        match verify_dns(expected) {
+            Ok(()) => {
+                log(&format!(
+                    "Verification {}/{}: DNS is {}",
+                    attempt,
+                    attempts,
+                    describe_servers(expected)
+                ));
+                return Ok(());
+            }
+            Err(err) if attempt < attempts => {
+                warn(&format!(
+                    "Verification {}/{} failed: {}, checking again",
+                    attempt, attempts, err
+                ));
+                attempt += 1;
+            }
+            Err(err) => {
+                warn(&format!(
+                    "Verification {}/{} failed: {}",
+                    attempt, attempts, err
+                ));
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Checks that the manually configured servers match `expected`.
+fn verify_dns(expected: &[String]) -> Result<(), Error> {
+    let actual = get_manual_dns()?;
+    if actual != expected {
+        return Err(Error::Verification {
+            expected: expected.to_vec(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Restores the original DNS servers when dropped, unless disarmed.
+///
+/// Holding a guard across a run means an error or panic after the settings
+/// were changed never leaves the machine worse off than it started.
+pub struct RollbackGuard {
+    original: Option<Vec<String>>,
+}
+
+impl RollbackGuard {
+    /// Creates a guard that will restore `original` (as returned by
+    /// [`get_manual_dns`]).
+    pub fn new(original: Vec<String>) -> Self {
+        RollbackGuard {
+            original: Some(original),
+        }
+    }
+
+    /// Keeps the current configuration, called once the run succeeded.
+    pub fn disarm(mut self) {
+        self.original = None;
+    }
+}
+
+impl Drop for RollbackGuard {
+    fn drop(&mut self) {
+        let Some(original) = self.original.take() else {
+            return;
+        };
+        warn("run did not complete, restoring original DNS configuration");
+        if let Err(err) = restore_dns(&original) {
+            error(&format!("failed to restore original DNS: {}", err));
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! TLS client connections for the DNS-over-TLS and DNS-over-HTTPS probes.
+
+use crate::proxy::{self, Proxy};
+use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+/// A TLS session over TCP.
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Client configuration trusting the Mozilla root certificates, built once.
+fn config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = ClientConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .expect("Failed to configure TLS")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Opens a TLS connection to `address`, through `proxy` if one is given,
+/// verifying the certificate against `host` (a hostname or an IP address),
+/// and completes the handshake.
+pub fn connect(
+    host: &str,
+    address: SocketAddr,
+    proxy: Option<&Proxy>,
+    timeout: Duration,
+) -> io::Result<TlsStream> {
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let connection = ClientConnection::new(config(), name).map_err(io::Error::other)?;
+    let socket = proxy::connect(proxy, address, timeout)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+
+    let mut stream = StreamOwned::new(connection, socket);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
+}
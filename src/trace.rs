@@ -0,0 +1,274 @@
+//! The decision trace for `--trace-json`: what a run measured and decided,
+//! from the raw probe timings to the apply outcome, gathered as it happens
+//! and written as one JSON document when the run ends. Attached to an issue,
+//! it lets a run be reconstructed without access to the network it ran on.
+
+use crate::{
+    backend::{self, ProbeSettings},
+    error::Error,
+    measurement::Measurement,
+    providers::Provider,
+    score::ScoreExpr,
+};
+use chrono::Local;
+use serde::Serialize;
+use std::{fs, path::Path, sync::Mutex, time::Duration};
+
+/// The trace being gathered, `None` unless `--trace-json` was given.
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+#[derive(Serialize)]
+struct Trace {
+    version: &'static str,
+    /// When the run started, RFC 3339 local time
+    started: String,
+    /// The command line, program name left out
+    arguments: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    settings: Option<Settings>,
+    servers: Vec<Server>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ranking: Option<Ranking>,
+    /// Servers taken out of the running after ranking, in order
+    filtered: Vec<Filtered>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    winner: Option<Winner>,
+    /// What happened to the system DNS, in order
+    outcome: Vec<String>,
+    /// The error the run ended with, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// How servers were probed.
+#[derive(Serialize)]
+struct Settings {
+    methods: Vec<String>,
+    fallback: bool,
+    count: usize,
+    warmup: u32,
+    timeout_ms: f64,
+    timeout_retries: u32,
+    probe_interval_ms: f64,
+    probe_name: String,
+    record_type: u16,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    record_types: Vec<&'static str>,
+    mocked: bool,
+    /// Seed of every random choice, `--seed` repeats them
+    seed: u64,
+}
+
+/// One measured server with every probe it answered.
+#[derive(Serialize)]
+struct Server {
+    name: String,
+    ip: String,
+    family: String,
+    baseline: bool,
+    sent: usize,
+    /// Round-trip time of every answered probe, in the order they came
+    samples_ms: Vec<f64>,
+    latency_ms: Option<f64>,
+    loss: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cold_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    setup_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u8>,
+    /// Results of the checks run on its answers, e.g. `correctness: ...`
+    checks: Vec<String>,
+}
+
+/// The order servers were ranked in.
+#[derive(Serialize)]
+struct Ranking {
+    strategy: String,
+    /// Servers that may be applied, best first
+    candidates: Vec<Ranked>,
+}
+
+#[derive(Serialize)]
+struct Ranked {
+    ip: String,
+    /// The `--score-expr` value, lower is better
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct Filtered {
+    ip: String,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct Winner {
+    name: String,
+    ip: String,
+    /// Every server that would be applied, the winner first
+    servers: Vec<String>,
+}
+
+/// Starts gathering a trace, written by [`finish`].
+pub fn init() {
+    *lock() = Some(Trace {
+        version: env!("CARGO_PKG_VERSION"),
+        started: Local::now().to_rfc3339(),
+        arguments: std::env::args().skip(1).collect(),
+        settings: None,
+        servers: Vec::new(),
+        ranking: None,
+        filtered: Vec::new(),
+        winner: None,
+        outcome: Vec::new(),
+        error: None,
+    });
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Trace>> {
+    TRACE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Runs `update` on the trace when one is being gathered.
+fn with(update: impl FnOnce(&mut Trace)) {
+    if let Some(trace) = lock().as_mut() {
+        update(trace);
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Records how servers are probed, and the seed of the run.
+pub fn settings(settings: &ProbeSettings, seed: u64) {
+    with(|trace| {
+        trace.settings = Some(Settings {
+            methods: settings
+                .methods
+                .iter()
+                .map(|method| backend::backend(*method).name().to_string())
+                .collect(),
+            fallback: settings.fallback,
+            count: settings.count,
+            warmup: settings.warmup,
+            timeout_ms: ms(settings.timeout),
+            timeout_retries: settings.timeout_retries,
+            probe_interval_ms: ms(settings.probe_interval),
+            probe_name: settings.probe_name.clone(),
+            record_type: settings.record_type,
+            record_types: settings
+                .record_types
+                .iter()
+                .map(|record_type| record_type.name())
+                .collect(),
+            mocked: settings.mock.is_some(),
+            seed,
+        })
+    });
+}
+
+/// Records the measurements of a benchmark, replacing earlier ones along
+/// with the decision made on them.
+pub fn measurements(results: &[(&Provider, Measurement)]) {
+    with(|trace| {
+        trace.ranking = None;
+        trace.filtered.clear();
+        trace.winner = None;
+        trace.servers = results
+            .iter()
+            .map(|(provider, measurement)| Server {
+                name: provider.name.clone(),
+                ip: provider.ip.clone(),
+                family: provider.family.clone(),
+                baseline: provider.baseline,
+                sent: measurement.sent,
+                samples_ms: measurement.samples.iter().copied().map(ms).collect(),
+                latency_ms: measurement.latency().map(ms),
+                loss: measurement.loss(),
+                cold_ms: measurement.cold.map(ms),
+                setup_ms: measurement.setup.map(ms),
+                ttl: measurement.ttl,
+                checks: Vec::new(),
+            })
+            .collect()
+    });
+}
+
+/// Records the result of a check on the server at `ip`.
+pub fn check(ip: &str, check: &str, result: impl std::fmt::Display) {
+    with(|trace| {
+        if let Some(server) = trace.servers.iter_mut().find(|server| server.ip == ip) {
+            server.checks.push(format!("{}: {}", check, result));
+        }
+    });
+}
+
+/// Records the ranked candidates, with their `--score-expr` scores.
+pub fn ranking(
+    strategy: String,
+    candidates: &[&Provider],
+    results: &[(&Provider, Measurement)],
+    score: Option<&ScoreExpr>,
+) {
+    with(|trace| {
+        let candidates = candidates
+            .iter()
+            .map(|provider| Ranked {
+                ip: provider.ip.clone(),
+                score: score.and_then(|expr| {
+                    let (_, measurement) =
+                        results.iter().find(|(other, _)| other.ip == provider.ip)?;
+                    expr.score(measurement)
+                }),
+            })
+            .collect();
+        trace.ranking = Some(Ranking {
+            strategy,
+            candidates,
+        });
+    });
+}
+
+/// Records that the server at `ip` was taken out of the running.
+pub fn filtered(ip: &str, reason: impl Into<String>) {
+    with(|trace| {
+        trace.filtered.push(Filtered {
+            ip: ip.to_string(),
+            reason: reason.into(),
+        })
+    });
+}
+
+/// Records the winner and the servers that would be applied with it.
+pub fn winner(winner: &Provider, servers: &[String]) {
+    with(|trace| {
+        trace.winner = Some(Winner {
+            name: winner.name.clone(),
+            ip: winner.ip.clone(),
+            servers: servers.to_vec(),
+        })
+    });
+}
+
+/// Records what a run did to the system DNS.
+pub fn outcome(message: &str) {
+    with(|trace| trace.outcome.push(message.to_string()));
+}
+
+/// Writes the trace to `path` with the error the run ended with, if any.
+pub fn finish(path: &Path, error: Option<&Error>) -> Result<(), Error> {
+    let Some(mut trace) = lock().take() else {
+        return Ok(());
+    };
+    trace.error = error.map(|error| error.to_string());
+    let json = serde_json::to_string_pretty(&trace).expect("trace serializes");
+    fs::write(path, json + "\n").map_err(|err| Error::Write {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    })
+}
@@ -0,0 +1,60 @@
+//! How resolvers deliver responses too large for a classic 512 byte
+//! datagram: over UDP thanks to EDNS, truncated with a retry over TCP, or
+//! not at all. Resolvers that get this wrong are slow on DNSSEC and the
+//! bigger modern record types even when they ping fast.
+
+use crate::{dns, source};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Name queried for a large response: the root zone's DNSSEC keys, well over
+/// 512 bytes with their signatures yet within [`dns::EDNS_PAYLOAD`].
+pub const LARGE_NAME: &str = ".";
+
+/// How the large response reached wisp.
+pub enum Delivery {
+    /// In one UDP datagram, with its size in bytes
+    Udp(usize),
+    /// Truncated over UDP, then in full over TCP, with its size in bytes
+    Tcp(usize),
+    /// Truncated over UDP and the TCP retry failed, with the reason
+    Truncated(String),
+}
+
+impl fmt::Display for Delivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delivery::Udp(size) => write!(f, "UDP with EDNS, {} bytes", size),
+            Delivery::Tcp(size) => write!(f, "fell back to TCP, {} bytes", size),
+            Delivery::Truncated(reason) => write!(f, "truncated, TCP retry failed ({})", reason),
+        }
+    }
+}
+
+/// Asks the resolver at `address` for the `DNSKEY` records of
+/// [`LARGE_NAME`] with EDNS and follows a truncated answer over TCP, as a
+/// stub resolver would. Returns how the response came and the time until it
+/// was complete, across both transports.
+pub fn check(address: &str, timeout: Duration) -> Result<(Delivery, Duration), String> {
+    let server = dns::resolve_server(address).map_err(|err| err.to_string())?;
+    let id = dns::query_id();
+    let request = dns::build_edns_query(id, LARGE_NAME, dns::TYPE_DNSKEY);
+    let (elapsed, packet) =
+        dns::exchange_packet(server, id, &request, timeout).map_err(|err| err.to_string())?;
+    if !dns::is_truncated(&packet) {
+        return Ok((Delivery::Udp(packet.len()), elapsed));
+    }
+    let start = Instant::now();
+    let response = source::tcp_connect(server, timeout).and_then(|mut stream| {
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        dns::exchange_stream_packet(&mut stream, id, &request)
+    });
+    let elapsed = elapsed + start.elapsed();
+    match response {
+        Ok(packet) => Ok((Delivery::Tcp(packet.len()), elapsed)),
+        Err(err) => Ok((Delivery::Truncated(err.to_string()), elapsed)),
+    }
+}